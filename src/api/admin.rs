@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use warp::{
+    http::{Method, StatusCode},
+    Filter, Reply,
+};
+
+use super::github::webhook_workflow_job_handler;
+use crate::config::GLOBAL_ADMIN_CONFIG;
+use crate::delivery_log;
+use crate::shutdown;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct DrainResponse {
+    pub draining: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct ReplayResponse {
+    pub delivery: String,
+    pub status: u16,
+}
+
+/// Check the bearer token, the admin API is disabled when no token is configured.
+fn is_authorized(authorization: Option<&str>) -> bool {
+    let admin_config = match &*GLOBAL_ADMIN_CONFIG {
+        Some(admin_config) if !admin_config.token.is_empty() => admin_config,
+        _ => return false,
+    };
+
+    let provided_token = match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(provided_token) => provided_token.as_bytes(),
+        None => return false,
+    };
+    let expected_token = admin_config.token.as_bytes();
+
+    // Constant time comparison.
+    provided_token.len() == expected_token.len()
+        && provided_token
+            .iter()
+            .zip(expected_token)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+async fn get_delivery_handler(
+    delivery: String,
+    authorization: Option<String>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    if !is_authorized(authorization.as_deref()) {
+        return Ok(Box::new(StatusCode::UNAUTHORIZED));
+    }
+
+    match delivery_log::find(delivery.as_str()) {
+        Some(stored_delivery) => Ok(Box::new(warp::reply::json(&stored_delivery))),
+        None => Ok(Box::new(StatusCode::NOT_FOUND)),
+    }
+}
+
+async fn replay_delivery_handler(
+    delivery: String,
+    authorization: Option<String>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    if !is_authorized(authorization.as_deref()) {
+        return Ok(Box::new(StatusCode::UNAUTHORIZED));
+    }
+
+    let stored_delivery = match delivery_log::find(delivery.as_str()) {
+        Some(stored_delivery) => stored_delivery,
+        None => return Ok(Box::new(StatusCode::NOT_FOUND)),
+    };
+
+    if stored_delivery.event != "workflow_job" {
+        return Ok(Box::new(StatusCode::UNPROCESSABLE_ENTITY));
+    }
+
+    info!("octoling: Replaying delivery {}", delivery);
+
+    let status = webhook_workflow_job_handler(stored_delivery.body.as_str(), true).await?;
+
+    Ok(Box::new(warp::reply::json(&ReplayResponse {
+        delivery,
+        status: status.as_u16(),
+    })))
+}
+
+async fn drain_handler(
+    method: Method,
+    authorization: Option<String>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    if !is_authorized(authorization.as_deref()) {
+        return Ok(Box::new(StatusCode::UNAUTHORIZED));
+    }
+
+    if method == Method::PUT {
+        shutdown::set_draining(true);
+    } else if method == Method::DELETE {
+        shutdown::set_draining(false);
+    }
+
+    Ok(Box::new(warp::reply::json(&DrainResponse {
+        draining: shutdown::is_draining(),
+    })))
+}
+
+fn get_delivery_route() -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "v0" / "deliveries" / String)
+        .and(warp::get())
+        .and(warp::header::optional("Authorization"))
+        .and_then(get_delivery_handler)
+}
+
+fn replay_delivery_route() -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "v0" / "deliveries" / String / "replay")
+        .and(warp::post())
+        .and(warp::header::optional("Authorization"))
+        .and_then(replay_delivery_handler)
+}
+
+// GET to query the drain mode, PUT to enable it and DELETE to disable it.
+fn drain_route() -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "v0" / "drain")
+        .and(
+            warp::get()
+                .or(warp::put())
+                .unify()
+                .or(warp::delete())
+                .unify(),
+        )
+        .and(warp::method())
+        .and(warp::header::optional("Authorization"))
+        .and_then(drain_handler)
+}
+
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone {
+    get_delivery_route()
+        .or(replay_delivery_route())
+        .or(drain_route())
+}
@@ -1,68 +1,95 @@
 use hex::FromHex;
-use hmac::{
-    digest::{consts::U32, generic_array::GenericArray},
-    Hmac, Mac, NewMac,
-};
-use serde::{Deserialize, Serialize};
+use hmac::{Hmac, Mac, NewMac};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer, Serialize};
 use sha2::Sha256;
 use std::convert::Infallible;
-use warp::{http::StatusCode, Filter};
+use std::sync::Mutex;
+use std::time::Duration;
+use warp::{
+    http::{HeaderMap, StatusCode},
+    Filter,
+};
 
-use crate::config::{self, GLOBAL_GITHUB_CONFIG, SHA256_SIZE};
+use crate::config::{
+    self, GithubConfig, PublicRepositoryPolicy, GLOBAL_WEBHOOK_CONFIG, SHA256_SIZE,
+};
+use crate::delivery_log::{self, StoredDelivery};
+use crate::jobs::{self, JobStatus};
 use crate::manager;
+use crate::scheduler::{self, RunnerRequest};
+use crate::utils::ExpiringSet;
 
 type HmacSha256 = Hmac<Sha256>;
 
+// Models only require the fields octoling relies on, GitHub omits or nulls the others depending on
+// the event or API endpoint. Unknown fields are ignored.
+
+/// Treat a null value like a missing one.
+fn deserialize_null_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct User {
     pub login: String,
     pub id: u64,
-    pub node_id: String,
-    pub avatar_url: String,
-    pub gravatar_id: String,
-    pub url: String,
-    pub html_url: String,
-    pub followers_url: String,
-    pub following_url: String,
-    pub gists_url: String,
-    pub starred_url: String,
-    pub subscriptions_url: String,
-    pub organizations_url: String,
-    pub repos_url: String,
-    pub events_url: String,
-    pub received_events_url: String,
+    pub node_id: Option<String>,
+    pub avatar_url: Option<String>,
+    pub gravatar_id: Option<String>,
+    pub url: Option<String>,
+    pub html_url: Option<String>,
+    pub followers_url: Option<String>,
+    pub following_url: Option<String>,
+    pub gists_url: Option<String>,
+    pub starred_url: Option<String>,
+    pub subscriptions_url: Option<String>,
+    pub organizations_url: Option<String>,
+    pub repos_url: Option<String>,
+    pub events_url: Option<String>,
+    pub received_events_url: Option<String>,
+    #[serde(rename = "type")]
+    pub user_type: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
+    pub site_admin: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Repository {
     pub id: u64,
-    pub node_id: String,
+    pub node_id: Option<String>,
     pub name: String,
     pub full_name: String,
     pub private: bool,
     pub owner: User,
-    pub html_url: String,
-    pub description: String,
+    pub html_url: Option<String>,
+    pub description: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
     pub fork: bool,
-    pub url: String,
-    pub visibility: String,
-    pub default_branch: String,
+    pub url: Option<String>,
+    pub visibility: Option<String>,
+    pub default_branch: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct WorkflowJob {
     pub id: u64,
     pub run_id: u64,
-    pub run_attempt: u64,
-    pub node_id: String,
-    pub head_sha: String,
-    pub url: String,
-    pub html_url: String,
+    pub run_attempt: Option<u64>,
+    pub node_id: Option<String>,
+    pub head_sha: Option<String>,
+    pub url: Option<String>,
+    pub html_url: Option<String>,
     pub status: String,
     pub conclusion: Option<String>,
-    pub started_at: String,
+    pub started_at: Option<String>,
     pub completed_at: Option<String>,
     pub name: String,
+    #[serde(default, deserialize_with = "deserialize_null_default")]
     pub labels: Vec<String>,
     pub runner_id: Option<u64>,
     pub runner_name: Option<String>,
@@ -77,9 +104,62 @@ pub struct WorkflowJobEvent {
     pub workflow_job: WorkflowJob,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct WorkflowRunRepository {
+    pub full_name: String,
+}
+
+/// The part of a workflow run telling where its code comes from.
+#[derive(Debug, Clone, Deserialize)]
+struct WorkflowRun {
+    pub head_repository: Option<WorkflowRunRepository>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebhookRepositoryOwner {
+    pub login: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct WebhookRepository {
+    pub name: String,
+    pub owner: WebhookRepositoryOwner,
+}
+
+/// The part of any event needed to know which repository it is about.
+#[derive(Debug, Clone, Deserialize)]
+struct WebhookEnvelope {
+    pub repository: Option<WebhookRepository>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PingEvent {
+    pub zen: Option<String>,
+    pub hook_id: Option<u64>,
+    pub repository: Option<WebhookRepository>,
+}
+
 const SHA256_HEX_SIZE: usize = 64;
 const SHA256_PREFIX: &str = "sha256=";
 
+// Events we care about are small, GitHub itself caps payloads at 25MiB.
+const MAX_WEBHOOK_BODY_SIZE: u64 = 1024 * 1024;
+
+static GLOBAL_DELIVERIES: Lazy<Mutex<ExpiringSet<String>>> = Lazy::new(|| {
+    Mutex::new(ExpiringSet::new(
+        Duration::from_secs(GLOBAL_WEBHOOK_CONFIG.retention),
+        GLOBAL_WEBHOOK_CONFIG.max_retained_deliveries,
+    ))
+});
+
+/// Record a delivery GUID, return false if the delivery was already received.
+fn mark_delivery_received(delivery: &str) -> bool {
+    GLOBAL_DELIVERIES
+        .lock()
+        .unwrap()
+        .insert(String::from(delivery))
+}
+
 fn get_runner_id_by_job_event(event: &WorkflowJobEvent) -> String {
     format!(
         "octoling-{}-{}-{}",
@@ -98,9 +178,89 @@ fn format_workflow_job_prefix(event: &WorkflowJobEvent) -> String {
     )
 }
 
-async fn handle_workflow_job_queued(event: WorkflowJobEvent) {
+/// Whether the workflow run of a job comes from another repository, like a pull request from a fork.
+async fn is_from_fork(github_config: &GithubConfig, event: &WorkflowJobEvent) -> Option<bool> {
+    // https://docs.github.com/en/rest/actions/workflow-runs#get-a-workflow-run
+    let workflow_run: WorkflowRun = github_config
+        .get_repo_json(format!("actions/runs/{}", event.workflow_job.run_id).as_str())
+        .await?;
+
+    // The head repository is null once the fork was deleted.
+    Some(match workflow_run.head_repository {
+        Some(head_repository) => !head_repository
+            .full_name
+            .eq_ignore_ascii_case(event.repository.full_name.as_str()),
+        None => true,
+    })
+}
+
+/// Check that a job may get a runner, return the reason otherwise.
+async fn check_job_policy(
+    github_config: &GithubConfig,
+    event: &WorkflowJobEvent,
+) -> Result<(), String> {
+    let policy = &github_config.policy;
+    let sender = event.sender.login.as_str();
+
+    if event.repository.fork && !policy.allow_fork_repositories {
+        return Err(String::from(
+            "jobs of repositories that are forks are not allowed",
+        ));
+    }
+
+    if policy.trusted_senders.is_some() && !policy.is_trusted_sender(sender) {
+        return Err(format!("sender {} is not trusted", sender));
+    }
+
+    if !event.repository.private {
+        match policy.public_repository {
+            PublicRepositoryPolicy::Allow => {}
+            PublicRepositoryPolicy::Deny => {
+                return Err(String::from("jobs of public repositories are not allowed"));
+            }
+            PublicRepositoryPolicy::TrustedSenders => {
+                if !policy.is_trusted_sender(sender) {
+                    return Err(format!(
+                        "sender {} is not trusted to run jobs of a public repository",
+                        sender
+                    ));
+                }
+            }
+        }
+    }
+
+    if !policy.allow_fork_pull_requests {
+        match is_from_fork(github_config, event).await {
+            Some(false) => {}
+            Some(true) => {
+                return Err(String::from(
+                    "jobs of workflow runs coming from forks are not allowed",
+                ))
+            }
+            None => {
+                return Err(String::from(
+                    "cannot check whether the workflow run comes from a fork",
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn handle_workflow_job_queued(event: WorkflowJobEvent) {
     let log_prefix = format_workflow_job_prefix(&event);
-    println!("{} queued", log_prefix);
+
+    if !jobs::mark_job_queued(
+        event.workflow_job.id,
+        event.repository.owner.login.as_str(),
+        event.repository.name.as_str(),
+    ) {
+        info!("{} queued event was already handled", log_prefix);
+        return;
+    }
+
+    info!("{} queued", log_prefix);
 
     let github_config = config::get_github_config_by_owner_and_repo(
         event.repository.owner.login.as_str(),
@@ -108,71 +268,196 @@ async fn handle_workflow_job_queued(event: WorkflowJobEvent) {
     );
 
     if let Some(github_config) = github_config {
-        for label in &event.workflow_job.labels {
-            if let Some(image_config) = config::get_image_config_by_label(label.as_str()) {
-                let runner_id = get_runner_id_by_job_event(&event);
-
-                println!("{} Creating and starting runner {}", log_prefix, runner_id);
-
-                let result = manager::start_new_runner(
-                    &image_config,
-                    github_config,
-                    image_config.labels[0].as_str(),
-                    runner_id.as_str(),
-                )
-                .await;
-
-                match result {
-                    Ok(_) => println!("{} Started runner {}", log_prefix, runner_id),
-                    Err(error) => eprintln!(
-                        "{} Cannot start runner {} for label \"{}\": {:?}",
-                        log_prefix, runner_id, label, error
-                    ),
-                }
+        if let Err(reason) = check_job_policy(&github_config, &event).await {
+            jobs::mark_job_ignored(event.workflow_job.id);
+
+            eprintln!("{} blocked by policy: {}", log_prefix, reason);
+            return;
+        }
 
-                return;
+        let owner = event.repository.owner.login.as_str();
+        let repository = event.repository.name.as_str();
+
+        for image_config in config::get_image_configs_by_labels(&event.workflow_job.labels) {
+            if !image_config.is_allowed_for_repository(owner, repository) {
+                eprintln!(
+                    "{} Denied image {}: not allowed for {}/{}",
+                    log_prefix, image_config.id, owner, repository
+                );
+                continue;
             }
+
+            let runner_id = get_runner_id_by_job_event(&event);
+
+            scheduler::submit(RunnerRequest::new(
+                image_config,
+                github_config,
+                runner_id,
+                event.workflow_job.id,
+                log_prefix,
+            ));
+
+            return;
         }
     }
 
-    println!("{} cannot be handled by this instance.", log_prefix);
+    jobs::mark_job_ignored(event.workflow_job.id);
+
+    info!("{} cannot be handled by this instance.", log_prefix);
 }
 
-async fn handle_workflow_job_completed(event: WorkflowJobEvent) {
+/// Why a job must not run on a runner of this instance, if it must not.
+async fn get_job_rejection_reason(event: &WorkflowJobEvent) -> Option<String> {
+    match jobs::get_job_info(event.workflow_job.id).map(|job_info| job_info.status) {
+        Some(JobStatus::Ignored) => {
+            return Some(String::from("it cannot be handled by this instance"))
+        }
+        // The policy was checked when the job was queued.
+        Some(JobStatus::Queued) | Some(JobStatus::InProgress) | Some(JobStatus::Completed) => {
+            return None
+        }
+        // The queued event was missed.
+        None => {}
+    }
+
+    let github_config = config::get_github_config_by_owner_and_repo(
+        event.repository.owner.login.as_str(),
+        event.repository.name.as_str(),
+    )?;
+
+    check_job_policy(&github_config, event)
+        .await
+        .err()
+        .map(|reason| format!("it is blocked by policy: {}", reason))
+}
+
+pub async fn handle_workflow_job_in_progress(event: WorkflowJobEvent) {
     let log_prefix = format_workflow_job_prefix(&event);
 
-    println!("{} completed", log_prefix);
+    let runner_name = match &event.workflow_job.runner_name {
+        Some(runner_name) => runner_name,
+        None => return,
+    };
+
+    // Jobs running on runners from elsewhere are none of our business.
+    let runner_info = match manager::get_runner_info(runner_name.as_str()) {
+        Some(runner_info) => runner_info,
+        None => return,
+    };
+
+    // GitHub gives a job to any idle runner with matching labels, even one provisioned for another job.
+    if let Some(reason) = get_job_rejection_reason(&event).await {
+        eprintln!(
+            "{} picked up by runner {} but {}, destroying the runner",
+            log_prefix, runner_name, reason
+        );
+
+        if let Err(error) = manager::destroy_runner_with_runner_id(runner_name.as_str()).await {
+            eprintln!(
+                "{} Cannot destroy runner {}: {:?}",
+                log_prefix, runner_name, error
+            );
+        }
+
+        // Forget the job the runner was provisioned for so that the sweeper gives it another runner.
+        let is_provisioned_job_waiting = runner_info.job_id != event.workflow_job.id
+            && jobs::get_job_info(runner_info.job_id)
+                .is_some_and(|job_info| job_info.status == JobStatus::Queued);
+
+        if is_provisioned_job_waiting {
+            jobs::forget_job(runner_info.job_id);
+        }
+
+        return;
+    }
+
+    if !jobs::mark_job_in_progress(
+        event.workflow_job.id,
+        event.repository.owner.login.as_str(),
+        event.repository.name.as_str(),
+        runner_name.as_str(),
+    ) {
+        return;
+    }
 
-    if let Some(runner_id) = &event.workflow_job.runner_name {
+    if manager::assign_job_to_runner(runner_name.as_str(), event.workflow_job.id) {
+        if runner_info.job_id == event.workflow_job.id {
+            info!("{} picked up by runner {}", log_prefix, runner_name);
+        } else {
+            info!(
+                "{} picked up by runner {} (provisioned for job #{})",
+                log_prefix, runner_name, runner_info.job_id
+            );
+        }
+    }
+}
+
+pub async fn handle_workflow_job_completed(event: WorkflowJobEvent) {
+    let log_prefix = format_workflow_job_prefix(&event);
+
+    if !jobs::mark_job_completed(event.workflow_job.id) {
+        info!("{} completed event was already handled", log_prefix);
+        return;
+    }
+
+    info!("{} completed", log_prefix);
+
+    // Prefer the runner recorded when the job was picked up if GitHub doesn't tell.
+    let runner_name = event.workflow_job.runner_name.clone().or_else(|| {
+        jobs::get_job_info(event.workflow_job.id).and_then(|job_info| job_info.runner_name)
+    });
+
+    if let Some(runner_id) = &runner_name {
         match manager::destroy_runner_with_runner_id(runner_id).await {
             Ok(()) => {
-                println!("{} {} was destroyed", log_prefix, runner_id);
+                info!("{} {} was destroyed", log_prefix, runner_id);
             }
             Err(error) => eprintln!(
                 "{} Cannot destroy runner {}: {:?}",
                 log_prefix, runner_id, error
             ),
         }
-    } else {
-        println!("{} cannot be handled by this instance.", log_prefix);
-    }
-}
 
-async fn webhook_workflow_job_handler(event_raw: &str) -> Result<StatusCode, Infallible> {
-    let event_parsing_result = serde_json::from_str(event_raw);
+        return;
+    }
 
-    if event_parsing_result.is_err() {
-        return Ok(StatusCode::BAD_REQUEST);
+    // The job was cancelled before any runner picked it up.
+    if scheduler::cancel(event.workflow_job.id) {
+        info!(
+            "{} was cancelled while waiting for capacity, dropping its runner request",
+            log_prefix
+        );
+        return;
     }
 
-    let event: WorkflowJobEvent = event_parsing_result.unwrap();
+    match manager::cancel_runner_for_job(event.workflow_job.id).await {
+        Ok(Some(runner_id)) => {
+            info!(
+                "{} was cancelled before being picked up, tearing down runner {}",
+                log_prefix, runner_id
+            );
+        }
+        Ok(None) => info!("{} cannot be handled by this instance.", log_prefix),
+        Err(error) => eprintln!(
+            "{} Cannot cancel runner of cancelled job: {:?}",
+            log_prefix, error
+        ),
+    }
+}
 
+/// Handle a workflow job event in the background, whether it comes from a webhook or the poller.
+pub fn dispatch_workflow_job_event(event: WorkflowJobEvent) {
     match event.workflow_job.status.as_str() {
         "queued" => {
             tokio::spawn(async move {
                 handle_workflow_job_queued(event).await;
             });
         }
+        "in_progress" => {
+            tokio::spawn(async move {
+                handle_workflow_job_in_progress(event).await;
+            });
+        }
         "completed" => {
             tokio::spawn(async move {
                 handle_workflow_job_completed(event).await;
@@ -180,48 +465,208 @@ async fn webhook_workflow_job_handler(event_raw: &str) -> Result<StatusCode, Inf
         }
         _ => {}
     }
+}
+
+/// Handle a raw workflow_job event, a replayed one is handled even if its job was already seen.
+///
+/// A replay is refused with a conflict when the job is completed or still has a runner.
+pub async fn webhook_workflow_job_handler(
+    event_raw: &str,
+    is_replay: bool,
+) -> Result<StatusCode, Infallible> {
+    let event: WorkflowJobEvent = match serde_json::from_str(event_raw) {
+        Ok(event) => event,
+        Err(error) => {
+            eprintln!("octoling: Cannot parse workflow_job event: {}", error);
+
+            return Ok(StatusCode::BAD_REQUEST);
+        }
+    };
+
+    if is_replay {
+        let job_id = event.workflow_job.id;
+        let is_completed = jobs::get_job_info(job_id)
+            .is_some_and(|job_info| job_info.status == JobStatus::Completed);
+
+        // Handling the job again would provision a second runner with the same name or one nothing would use.
+        if is_completed || scheduler::is_job_queued(job_id) || manager::has_runner_for_job(job_id) {
+            info!(
+                "octoling: Job #{} is completed or still has a runner, not replaying it",
+                job_id
+            );
+
+            return Ok(StatusCode::CONFLICT);
+        }
+
+        jobs::forget_job(job_id);
+    }
+
+    dispatch_workflow_job_event(event);
+
+    Ok(StatusCode::OK)
+}
+
+fn webhook_ping_handler(event_raw: &str) -> Result<StatusCode, Infallible> {
+    let event: PingEvent = match serde_json::from_str(event_raw) {
+        Ok(event) => event,
+        Err(_) => return Ok(StatusCode::BAD_REQUEST),
+    };
+
+    let source = match &event.repository {
+        Some(repository) => format!("{}/{}", repository.owner.login, repository.name),
+        None => String::from("unknown repository"),
+    };
+
+    info!(
+        "octoling: Ping from hook {} ({}): {}",
+        event
+            .hook_id
+            .map_or_else(|| String::from("?"), |hook_id| hook_id.to_string()),
+        source,
+        event.zen.unwrap_or_default()
+    );
 
     Ok(StatusCode::OK)
 }
 
+fn parse_signature(signature: &str) -> Option<[u8; SHA256_SIZE]> {
+    if signature.len() != SHA256_HEX_SIZE + SHA256_PREFIX.len()
+        || !signature.starts_with(SHA256_PREFIX)
+    {
+        return None;
+    }
+
+    <[u8; SHA256_SIZE]>::from_hex(&signature[SHA256_PREFIX.len()..]).ok()
+}
+
+fn verify_signature(secret: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    match HmacSha256::new_from_slice(secret) {
+        Ok(mut hasher) => {
+            hasher.update(data);
+
+            // Constant time comparison.
+            hasher.verify(signature).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
 async fn webhook_handler(
     event_type: String,
     signature: String,
+    delivery: Option<String>,
+    headers: HeaderMap,
     data: bytes::Bytes,
 ) -> Result<impl warp::Reply, Infallible> {
-    if signature.len() != SHA256_HEX_SIZE + SHA256_PREFIX.len()
-        || !signature.starts_with(SHA256_PREFIX)
-    {
-        return Ok(StatusCode::BAD_REQUEST);
+    let signature = match parse_signature(signature.as_str()) {
+        Some(signature) => signature,
+        None => return Ok(StatusCode::BAD_REQUEST),
+    };
+
+    let event_raw = match std::str::from_utf8(data.as_ref()) {
+        Ok(event_raw) => event_raw,
+        Err(_) => return Ok(StatusCode::BAD_REQUEST),
+    };
+
+    // The repository isn't trusted yet, but only its secrets can verify the signature.
+    let envelope: WebhookEnvelope = match serde_json::from_str(event_raw) {
+        Ok(envelope) => envelope,
+        Err(_) => return Ok(StatusCode::BAD_REQUEST),
+    };
+    let github_config = envelope.repository.as_ref().and_then(|repository| {
+        config::get_github_config_by_owner_and_repo(
+            repository.owner.login.as_str(),
+            repository.name.as_str(),
+        )
+    });
+
+    // Secrets of the repository come first, then the global ones used by organization webhooks.
+    let mut secrets = Vec::new();
+
+    if let Some(github_config) = &github_config {
+        for (index, secret) in github_config.get_webhook_secrets().into_iter().enumerate() {
+            let secret_name = format!(
+                "secret #{} of {}/{}",
+                index, github_config.owner, github_config.repository
+            );
+
+            secrets.push((secret_name, secret));
+        }
+    }
+
+    for (index, secret) in GLOBAL_WEBHOOK_CONFIG.secrets.iter().enumerate() {
+        secrets.push((format!("global secret #{}", index), secret.as_str()));
     }
 
-    let provided_hex_signature =
-        <[u8; SHA256_SIZE]>::from_hex(&signature[SHA256_PREFIX.len()..]).unwrap();
-    let expected_signature = GenericArray::<u8, U32>::from(provided_hex_signature);
+    let secret_name = secrets
+        .into_iter()
+        .find(|(_, secret)| verify_signature(secret.as_bytes(), &data, &signature))
+        .map(|(secret_name, _)| secret_name);
+
+    let secret_name = match secret_name {
+        Some(secret_name) => secret_name,
+        None => {
+            eprintln!(
+                "octoling: Rejected {} event {} with no matching secret",
+                event_type,
+                delivery.as_deref().unwrap_or("?")
+            );
+
+            return Ok(StatusCode::UNAUTHORIZED);
+        }
+    };
+
+    info!(
+        "octoling: {} event {} verified with {}",
+        event_type,
+        delivery.as_deref().unwrap_or("?"),
+        secret_name
+    );
 
-    for github_config in GLOBAL_GITHUB_CONFIG.clone() {
-        let mut hasher =
-            HmacSha256::new_from_slice(github_config.get_webhook_secret_slice()).unwrap();
+    // Organization webhooks also deliver the events of repositories octoling doesn't manage.
+    if let Some(repository) = &envelope.repository {
+        let owner = repository.owner.login.as_str();
+        let name = repository.name.as_str();
+
+        if github_config.is_none() {
+            debug!(
+                "octoling: Ignored {} event {} of unconfigured repository {}/{}",
+                event_type,
+                delivery.as_deref().unwrap_or("?"),
+                owner,
+                name
+            );
+
+            return Ok(StatusCode::OK);
+        }
+    }
 
-        hasher.update(&data);
+    let headers = headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), String::from(value.to_str().ok()?))))
+        .collect();
 
-        let computed_signature = hasher.finalize().into_bytes();
+    delivery_log::record(&StoredDelivery::new(
+        delivery.as_deref(),
+        event_type.as_str(),
+        headers,
+        event_raw,
+    ));
 
-        if computed_signature == expected_signature {
-            return match std::str::from_utf8(data.as_ref()) {
-                Ok(event_raw) => {
-                    if event_type == "workflow_job" {
-                        webhook_workflow_job_handler(event_raw).await
-                    } else {
-                        Ok(StatusCode::OK)
-                    }
-                }
-                Err(_) => Ok(StatusCode::INTERNAL_SERVER_ERROR),
-            };
+    // GitHub redelivers webhooks, acknowledge duplicates without handling them again.
+    if let Some(delivery) = &delivery {
+        if !mark_delivery_received(delivery.as_str()) {
+            info!("octoling: Delivery {} was already received", delivery);
+
+            return Ok(StatusCode::OK);
         }
     }
 
-    Ok(StatusCode::UNAUTHORIZED)
+    match event_type.as_str() {
+        "ping" => webhook_ping_handler(event_raw),
+        "workflow_job" => webhook_workflow_job_handler(event_raw, false).await,
+        _ => Ok(StatusCode::OK),
+    }
 }
 
 pub fn webhook_routes() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
@@ -230,6 +675,127 @@ pub fn webhook_routes() -> impl Filter<Extract = impl warp::Reply, Error = warp:
         .and(warp::post())
         .and(warp::header::header("X-GitHub-Event"))
         .and(warp::header::header("X-Hub-Signature-256"))
+        .and(warp::header::optional("X-GitHub-Delivery"))
+        .and(warp::header::headers_cloned())
+        .and(warp::body::content_length_limit(MAX_WEBHOOK_BODY_SIZE))
         .and(warp::body::bytes())
         .and_then(webhook_handler)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORKFLOW_JOB_QUEUED: &str = include_str!("../../tests/fixtures/workflow_job_queued.json");
+    const WORKFLOW_JOB_IN_PROGRESS: &str =
+        include_str!("../../tests/fixtures/workflow_job_in_progress.json");
+    const WORKFLOW_JOB_COMPLETED: &str =
+        include_str!("../../tests/fixtures/workflow_job_completed.json");
+    const WORKFLOW_JOB_NULL_FIELDS: &str =
+        include_str!("../../tests/fixtures/workflow_job_null_fields.json");
+    const PING_REPOSITORY: &str = include_str!("../../tests/fixtures/ping_repository.json");
+    const PING_ORGANIZATION: &str = include_str!("../../tests/fixtures/ping_organization.json");
+
+    fn parse_workflow_job_event(event_raw: &str) -> WorkflowJobEvent {
+        serde_json::from_str(event_raw).expect("cannot parse workflow_job event")
+    }
+
+    #[test]
+    fn parse_workflow_job_queued() {
+        let event = parse_workflow_job_event(WORKFLOW_JOB_QUEUED);
+
+        assert_eq!(event.workflow_job.id, 4071526394);
+        assert_eq!(event.workflow_job.status, "queued");
+        assert_eq!(
+            event.workflow_job.labels,
+            ["self-hosted", "linux", "ubuntu-20.04"]
+        );
+        assert_eq!(event.workflow_job.runner_name, None);
+        assert_eq!(event.repository.owner.login, "octo-org");
+        assert_eq!(event.repository.name, "hello-world");
+        assert!(!event.repository.private);
+        assert!(!event.repository.fork);
+        assert_eq!(event.sender.login, "octocat");
+        assert_eq!(
+            get_runner_id_by_job_event(&event),
+            "octoling-octo-org-hello-world-4071526394"
+        );
+    }
+
+    #[test]
+    fn parse_workflow_job_in_progress() {
+        let event = parse_workflow_job_event(WORKFLOW_JOB_IN_PROGRESS);
+
+        assert_eq!(event.workflow_job.status, "in_progress");
+        assert_eq!(
+            event.workflow_job.runner_name.as_deref(),
+            Some("octoling-octo-org-hello-world-4071526394")
+        );
+        assert_eq!(event.workflow_job.runner_group_id, Some(1));
+    }
+
+    #[test]
+    fn parse_workflow_job_completed() {
+        let event = parse_workflow_job_event(WORKFLOW_JOB_COMPLETED);
+
+        assert_eq!(event.workflow_job.status, "completed");
+        assert_eq!(event.workflow_job.conclusion.as_deref(), Some("success"));
+        assert!(event.workflow_job.completed_at.is_some());
+    }
+
+    #[test]
+    fn parse_workflow_job_with_missing_and_null_fields() {
+        let event = parse_workflow_job_event(WORKFLOW_JOB_NULL_FIELDS);
+
+        assert_eq!(event.workflow_job.status, "queued");
+        assert!(event.workflow_job.labels.is_empty());
+        assert_eq!(event.workflow_job.run_attempt, None);
+        assert_eq!(event.workflow_job.runner_group_name, None);
+        assert!(event.repository.private);
+        assert!(!event.repository.fork);
+        assert_eq!(event.repository.visibility, None);
+        assert_eq!(event.repository.owner.user_type, None);
+        assert!(!event.sender.site_admin);
+    }
+
+    #[test]
+    fn parse_repository_ping() {
+        let event: PingEvent = serde_json::from_str(PING_REPOSITORY).unwrap();
+        let repository = event.repository.unwrap();
+
+        assert_eq!(event.hook_id, Some(326915634));
+        assert_eq!(repository.owner.login, "octo-org");
+        assert_eq!(repository.name, "hello-world");
+        assert_eq!(webhook_ping_handler(PING_REPOSITORY), Ok(StatusCode::OK));
+    }
+
+    #[test]
+    fn parse_organization_ping() {
+        let event: PingEvent = serde_json::from_str(PING_ORGANIZATION).unwrap();
+
+        assert_eq!(event.hook_id, Some(326915700));
+        assert!(event.repository.is_none());
+        assert_eq!(webhook_ping_handler(PING_ORGANIZATION), Ok(StatusCode::OK));
+    }
+
+    #[test]
+    fn parse_envelope_of_every_event() {
+        for event_raw in &[
+            WORKFLOW_JOB_QUEUED,
+            WORKFLOW_JOB_IN_PROGRESS,
+            WORKFLOW_JOB_COMPLETED,
+            WORKFLOW_JOB_NULL_FIELDS,
+            PING_REPOSITORY,
+        ] {
+            let envelope: WebhookEnvelope = serde_json::from_str(event_raw).unwrap();
+            let repository = envelope.repository.unwrap();
+
+            assert_eq!(repository.owner.login, "octo-org");
+            assert_eq!(repository.name, "hello-world");
+        }
+
+        let envelope: WebhookEnvelope = serde_json::from_str(PING_ORGANIZATION).unwrap();
+
+        assert!(envelope.repository.is_none());
+    }
+}
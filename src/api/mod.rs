@@ -1,13 +1,16 @@
+mod admin;
 mod github;
 
 use serde::{Deserialize, Serialize};
 use warp::Filter;
 
-use crate::config::SERVER_VERSION;
+use crate::config::{GLOBAL_SERVER_CONFIG, SERVER_VERSION};
+use crate::health::{self, HealthStatus};
 
 // TODO:
 //pub use github::routes as github_connector_routes;
 pub use github::webhook_routes as github_webhook_routes;
+pub use github::{dispatch_workflow_job_event, Repository, User, WorkflowJob, WorkflowJobEvent};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 struct ApiVersionResponse {
@@ -28,6 +31,46 @@ fn api_version_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::
         .map(api_version_handler)
 }
 
+#[derive(Debug, Clone, Serialize)]
+struct HealthResponse {
+    pub healthy: bool,
+    /// Images that failed recently, the others are healthy.
+    pub images: Vec<HealthStatus>,
+}
+
+fn health_handler() -> impl warp::Reply {
+    let images = health::get_health_statuses();
+
+    warp::reply::json(&HealthResponse {
+        healthy: images.iter().all(|health_status| health_status.healthy),
+        images,
+    })
+}
+
+fn health_route() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    warp::path!("api" / "v0" / "health")
+        .and(warp::get())
+        .map(health_handler)
+}
+
 pub fn api_routes() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    // The admin API is only served on its unix socket when there is one.
+    let admin_over_tcp = warp::any()
+        .and_then(|| async {
+            if GLOBAL_SERVER_CONFIG.admin_socket.is_none() {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            }
+        })
+        .untuple_one();
+
     api_version_route()
+        .or(health_route())
+        .or(admin_over_tcp.and(admin::routes()))
+}
+
+pub fn admin_socket_routes(
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    admin::routes()
 }
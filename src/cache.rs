@@ -0,0 +1,245 @@
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::GLOBAL_CACHE_CONFIG;
+
+const CHECKSUM_EXTENSION: &str = "sha256";
+
+// Makes the temporary file of every download unique, even within the same process.
+static GLOBAL_DOWNLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CacheError {
+    Io(String),
+    DownloadFailed(String),
+    ChecksumMismatch { expected: String, actual: String },
+}
+
+impl From<std::io::Error> for CacheError {
+    fn from(err: std::io::Error) -> CacheError {
+        CacheError::Io(err.to_string())
+    }
+}
+
+impl From<tokio::task::JoinError> for CacheError {
+    fn from(err: tokio::task::JoinError) -> CacheError {
+        CacheError::Io(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for CacheError {
+    fn from(err: reqwest::Error) -> CacheError {
+        CacheError::DownloadFailed(err.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CacheError>;
+
+/// Files downloaded on the host and pushed into the runner during setup.
+///
+/// A `None` entry means the runner should download it by itself.
+#[derive(Clone, Debug, Default)]
+pub struct ProvisioningArtifacts {
+    pub runner_archive: Option<PathBuf>,
+    pub docker_install_script: Option<PathBuf>,
+}
+
+fn get_checksum_path(path: &Path) -> PathBuf {
+    let mut checksum_path = path.as_os_str().to_owned();
+
+    checksum_path.push(".");
+    checksum_path.push(CHECKSUM_EXTENSION);
+
+    PathBuf::from(checksum_path)
+}
+
+fn compute_file_checksum(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 0x10000];
+
+    loop {
+        let read_size = file.read(&mut buffer)?;
+
+        if read_size == 0 {
+            break;
+        }
+
+        hasher.update(&buffer[..read_size]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn ensure_checksum(expected: Option<&str>, actual: &str) -> Result<()> {
+    if let Some(expected) = expected {
+        if !expected.eq_ignore_ascii_case(actual) {
+            return Err(CacheError::ChecksumMismatch {
+                expected: String::from(expected),
+                actual: String::from(actual),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a previously downloaded file is still intact.
+///
+/// Files without an expected checksum can change upstream, they are only kept for `max_age`.
+fn is_cached_file_valid(path: &Path, expected_sha256: Option<&str>, max_age: Duration) -> bool {
+    let checksum_path = get_checksum_path(path);
+
+    if expected_sha256.is_none() {
+        let age = fs::metadata(&checksum_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+
+        match age {
+            Some(age) if age <= max_age => {}
+            _ => return false,
+        }
+    }
+
+    let stored_checksum = match fs::read_to_string(checksum_path) {
+        Ok(stored_checksum) => stored_checksum,
+        Err(_) => return false,
+    };
+    let stored_checksum = stored_checksum.trim();
+
+    match compute_file_checksum(path) {
+        Ok(checksum) => {
+            checksum == stored_checksum && ensure_checksum(expected_sha256, &checksum).is_ok()
+        }
+        Err(_) => false,
+    }
+}
+
+async fn download(url: &str, path: &Path, expected_sha256: Option<&str>) -> Result<()> {
+    // Download to a temporary file first so that concurrent fetches never observe a partial file.
+    let temporary_path = path.with_extension(format!(
+        "part-{}-{}",
+        std::process::id(),
+        GLOBAL_DOWNLOAD_COUNTER.fetch_add(1, Ordering::SeqCst)
+    ));
+    let result = download_to(url, &temporary_path, expected_sha256).await;
+
+    let checksum = match result {
+        Ok(checksum) => checksum,
+        Err(error) => {
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+
+            return Err(error);
+        }
+    };
+
+    tokio::fs::rename(&temporary_path, path).await?;
+    tokio::fs::write(get_checksum_path(path), checksum).await?;
+
+    Ok(())
+}
+
+/// Download `url` to `path`, return the checksum of the downloaded file.
+async fn download_to(url: &str, path: &Path, expected_sha256: Option<&str>) -> Result<String> {
+    let mut response = reqwest::Client::new()
+        .get(url)
+        .header("User-Agent", "octoling")
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut hasher = Sha256::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+
+    file.sync_all().await?;
+
+    let checksum = hex::encode(hasher.finalize());
+
+    ensure_checksum(expected_sha256, checksum.as_str())?;
+
+    Ok(checksum)
+}
+
+/// Return the path of `file_name` in the cache directory, downloading it from `url` if needed.
+pub async fn fetch(
+    directory: &Path,
+    url: &str,
+    file_name: &str,
+    expected_sha256: Option<&str>,
+    max_age: Duration,
+) -> Result<PathBuf> {
+    let path = directory.join(file_name);
+
+    // Hashing a large archive must not block the runtime.
+    let is_valid = {
+        let path = path.clone();
+        let expected_sha256 = expected_sha256.map(String::from);
+
+        tokio::task::spawn_blocking(move || {
+            is_cached_file_valid(&path, expected_sha256.as_deref(), max_age)
+        })
+        .await?
+    };
+
+    if is_valid {
+        return Ok(path);
+    }
+
+    tokio::fs::create_dir_all(directory).await?;
+
+    info!("octoling: Downloading {} to cache", url);
+
+    download(url, &path, expected_sha256).await?;
+
+    Ok(path)
+}
+
+/// Fetch every artifact needed by the runner setup.
+///
+/// When the cache is disabled, no artifacts are returned and the runner downloads them by itself.
+pub async fn fetch_provisioning_artifacts(
+    runner_url: &str,
+    docker_install_script_url: &str,
+) -> Result<ProvisioningArtifacts> {
+    let cache_config = match &*GLOBAL_CACHE_CONFIG {
+        Some(cache_config) if cache_config.enabled => cache_config,
+        _ => return Ok(ProvisioningArtifacts::default()),
+    };
+
+    let directory = Path::new(cache_config.directory.as_str());
+    let max_age = Duration::from_secs(cache_config.max_age);
+    let runner_file_name = runner_url.rsplit('/').next().unwrap_or("runner.tar.gz");
+
+    let runner_archive = fetch(
+        directory,
+        runner_url,
+        runner_file_name,
+        cache_config.runner_sha256.as_deref(),
+        max_age,
+    )
+    .await?;
+    let docker_install_script = fetch(
+        directory,
+        docker_install_script_url,
+        "install_docker.sh",
+        None,
+        max_age,
+    )
+    .await?;
+
+    Ok(ProvisioningArtifacts {
+        runner_archive: Some(runner_archive),
+        docker_install_script: Some(docker_install_script),
+    })
+}
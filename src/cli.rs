@@ -0,0 +1,387 @@
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, GLOBAL_GITHUB_CONFIG};
+use crate::logging::LogLevel;
+use crate::manager;
+
+pub const USAGE: &str = "Usage: octoling [OPTIONS] [COMMAND]
+
+Commands:
+    serve                    Handle GitHub webhooks and manage runners (default)
+    validate-config          Check the configuration file and exit
+    list-runners             List the runners registered on GitHub by octoling
+    destroy <runner>         Destroy a runner and deregister it from GitHub
+    gc                       Deregister the offline runners left on GitHub without a container
+    provision --image <id>   Create and start a container with an image, without installing or
+                             registering a runner, for manual testing
+    help                     Print this message (also -h and --help)
+
+Options:
+    --config <path>          Configuration file (default: $CONFIG_FILE or octoling.toml)
+    --listen <address:port>  Address the server listens on
+    --log-level <level>      error, info or debug (default: info)";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Serve,
+    ValidateConfig,
+    ListRunners,
+    Destroy { runner_id: String },
+    Gc,
+    Provision { image_id: String },
+    Help,
+}
+
+#[derive(Clone, Debug)]
+pub struct Arguments {
+    pub command: Command,
+    pub config_path: Option<String>,
+    pub listen: Option<SocketAddr>,
+    pub log_level: Option<LogLevel>,
+}
+
+/// Parse the command line, without the program name.
+pub fn parse<I>(args: I) -> Result<Arguments, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let mut positionals = Vec::new();
+    let mut config_path = None;
+    let mut listen = None;
+    let mut log_level = None;
+    let mut image_id = None;
+
+    while let Some(arg) = args.next() {
+        // Help is printed whatever else is on the command line.
+        if arg == "-h" || arg == "--help" {
+            return Ok(Arguments {
+                command: Command::Help,
+                config_path: None,
+                listen: None,
+                log_level: None,
+            });
+        }
+
+        if !arg.starts_with('-') {
+            positionals.push(arg);
+            continue;
+        }
+
+        // Accept both "--name value" and "--name=value".
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (String::from(name), Some(String::from(value))),
+            None => (arg, None),
+        };
+
+        if !matches!(
+            name.as_str(),
+            "--config" | "--listen" | "--log-level" | "--image"
+        ) {
+            return Err(format!("unknown option {}", name));
+        }
+
+        // The next argument is another option when the value was forgotten.
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) if !value.starts_with('-') => value,
+            _ => return Err(format!("missing value for {}", name)),
+        };
+
+        match name.as_str() {
+            "--config" => config_path = Some(value),
+            "--listen" => {
+                listen = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid listen address \"{}\"", value))?,
+                )
+            }
+            "--log-level" => log_level = Some(value.parse()?),
+            "--image" => image_id = Some(value),
+            _ => unreachable!(),
+        }
+    }
+
+    let mut positionals = positionals.into_iter();
+
+    let command = match positionals.next().as_deref() {
+        None | Some("serve") => Command::Serve,
+        Some("validate-config") => Command::ValidateConfig,
+        Some("list-runners") => Command::ListRunners,
+        Some("destroy") => match positionals.next() {
+            Some(runner_id) => Command::Destroy { runner_id },
+            None => return Err(String::from("destroy requires a runner name")),
+        },
+        Some("gc") => Command::Gc,
+        Some("provision") => match image_id.take() {
+            Some(image_id) => Command::Provision { image_id },
+            None => return Err(String::from("provision requires --image <id>")),
+        },
+        Some("help") => Command::Help,
+        Some(command) => return Err(format!("unknown command \"{}\"", command)),
+    };
+
+    if let Some(arg) = positionals.next() {
+        return Err(format!("unexpected argument \"{}\"", arg));
+    }
+
+    if image_id.is_some() {
+        return Err(String::from("--image is only accepted by provision"));
+    }
+
+    Ok(Arguments {
+        command,
+        config_path,
+        listen,
+        log_level,
+    })
+}
+
+/// Load the configuration, report every problem found and return false if it is invalid.
+pub fn load_config() -> bool {
+    match config::load() {
+        Ok(()) => true,
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("octoling: {}", error);
+            }
+
+            eprintln!(
+                "octoling: Found {} problem(s) in the configuration",
+                errors.len()
+            );
+
+            false
+        }
+    }
+}
+
+pub fn validate_config() -> bool {
+    if !load_config() {
+        return false;
+    }
+
+    println!("octoling: Configuration is valid");
+
+    true
+}
+
+pub async fn list_runners() -> bool {
+    let mut success = true;
+
+    for github_config in GLOBAL_GITHUB_CONFIG.iter() {
+        if !github_config.enabled {
+            continue;
+        }
+
+        let github_runners = match github_config.list_repo_runners().await {
+            Some(github_runners) => github_runners,
+            None => {
+                eprintln!(
+                    "octoling: Cannot list runners of {}/{}",
+                    github_config.owner, github_config.repository
+                );
+                success = false;
+                continue;
+            }
+        };
+
+        let runner_name_prefix = github_config.get_runner_name_prefix();
+
+        for github_runner in github_runners {
+            if !github_runner.name.starts_with(runner_name_prefix.as_str()) {
+                continue;
+            }
+
+            println!(
+                "{}/{}\t{}\t{}\t{}",
+                github_config.owner,
+                github_config.repository,
+                github_runner.name,
+                github_runner.status,
+                if github_runner.busy { "busy" } else { "idle" }
+            );
+        }
+    }
+
+    success
+}
+
+pub async fn destroy(runner_id: &str) -> bool {
+    match manager::destroy_runner_with_runner_id(runner_id).await {
+        Ok(()) => {
+            println!("octoling: Runner {} was destroyed", runner_id);
+
+            true
+        }
+        Err(error) => {
+            eprintln!("octoling: Cannot destroy runner {}: {:?}", runner_id, error);
+
+            false
+        }
+    }
+}
+
+/// Deregister the stale runners right away, the runners of a running server are spared as long as their
+/// container exists.
+pub async fn gc() -> bool {
+    manager::reconcile(Duration::ZERO).await;
+
+    true
+}
+
+/// Create and start a clean container with an image, nothing is installed or registered on GitHub in it and
+/// it must be destroyed manually.
+pub async fn provision(image_id: &str) -> bool {
+    let image_config = match config::get_image_config_by_id(image_id) {
+        Some(image_config) => image_config,
+        None => {
+            eprintln!("octoling: Unknown image {}", image_id);
+
+            return false;
+        }
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let runner_id = format!("octoling-manual-{}", timestamp);
+
+    for provider_id in image_config.get_provider_ids() {
+        match manager::start_new_clean_runner(&image_config, provider_id, runner_id.as_str()).await
+        {
+            Ok(_) => {
+                println!(
+                    "octoling: Runner {} started on provider {}, destroy it with \"octoling destroy {}\"",
+                    runner_id, provider_id, runner_id
+                );
+
+                return true;
+            }
+            Err(error) => eprintln!(
+                "octoling: Cannot start runner {} on provider {}: {:?}",
+                runner_id, provider_id, error
+            ),
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(args: &str) -> Result<Arguments, String> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    fn parse_command(args: &str) -> Result<Command, String> {
+        parse_str(args).map(|arguments| arguments.command)
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(parse_command(""), Ok(Command::Serve));
+        assert_eq!(parse_command("serve"), Ok(Command::Serve));
+        assert_eq!(
+            parse_command("validate-config"),
+            Ok(Command::ValidateConfig)
+        );
+        assert_eq!(parse_command("list-runners"), Ok(Command::ListRunners));
+        assert_eq!(
+            parse_command("destroy octoling-runner"),
+            Ok(Command::Destroy {
+                runner_id: String::from("octoling-runner")
+            })
+        );
+        assert_eq!(parse_command("gc"), Ok(Command::Gc));
+        assert_eq!(
+            parse_command("provision --image focal"),
+            Ok(Command::Provision {
+                image_id: String::from("focal")
+            })
+        );
+        assert_eq!(parse_command("help"), Ok(Command::Help));
+    }
+
+    #[test]
+    fn help_flags_win_over_everything_else() {
+        assert_eq!(parse_command("-h"), Ok(Command::Help));
+        assert_eq!(parse_command("--help"), Ok(Command::Help));
+        assert_eq!(parse_command("serve --help"), Ok(Command::Help));
+        assert_eq!(
+            parse_command("destroy octoling-runner -h"),
+            Ok(Command::Help)
+        );
+        assert_eq!(parse_command("unknown -h"), Ok(Command::Help));
+    }
+
+    #[test]
+    fn options_accept_separate_and_inline_values() {
+        let arguments =
+            parse_str("--config octoling.toml --listen=0.0.0.0:8080 --log-level=debug gc").unwrap();
+
+        assert_eq!(arguments.command, Command::Gc);
+        assert_eq!(arguments.config_path.as_deref(), Some("octoling.toml"));
+        assert_eq!(arguments.listen, Some("0.0.0.0:8080".parse().unwrap()));
+        assert_eq!(arguments.log_level, Some(LogLevel::Debug));
+
+        assert_eq!(
+            parse_command("provision --image=focal"),
+            Ok(Command::Provision {
+                image_id: String::from("focal")
+            })
+        );
+    }
+
+    #[test]
+    fn missing_values_are_rejected() {
+        assert_eq!(
+            parse_command("--config"),
+            Err(String::from("missing value for --config"))
+        );
+        assert_eq!(
+            parse_command("--config --log-level debug"),
+            Err(String::from("missing value for --config"))
+        );
+        assert_eq!(
+            parse_command("provision --image -x"),
+            Err(String::from("missing value for --image"))
+        );
+        assert_eq!(
+            parse_command("provision"),
+            Err(String::from("provision requires --image <id>"))
+        );
+        assert_eq!(
+            parse_command("destroy"),
+            Err(String::from("destroy requires a runner name"))
+        );
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        assert_eq!(
+            parse_command("gc --image focal"),
+            Err(String::from("--image is only accepted by provision"))
+        );
+        assert_eq!(parse_command("-x"), Err(String::from("unknown option -x")));
+        assert_eq!(
+            parse_command("--unknown value"),
+            Err(String::from("unknown option --unknown"))
+        );
+        assert_eq!(
+            parse_command("unknown"),
+            Err(String::from("unknown command \"unknown\""))
+        );
+        assert_eq!(
+            parse_command("gc extra"),
+            Err(String::from("unexpected argument \"extra\""))
+        );
+        assert_eq!(
+            parse_command("--listen nowhere"),
+            Err(String::from("invalid listen address \"nowhere\""))
+        );
+    }
+}
@@ -1,8 +1,10 @@
-use once_cell::sync::Lazy;
-use serde::Deserialize;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::env;
 use std::fs::File;
 use std::io::Read;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
 
 pub const SHA256_SIZE: usize = 32;
 pub const SERVER_VERSION: &str = "1.0.0";
@@ -15,6 +17,28 @@ pub struct Config {
     pub provider_configs: Option<Vec<ProviderConfig>>,
     #[serde(rename = "image")]
     pub image_configs: Option<Vec<ImageConfig>>,
+    #[serde(rename = "cache")]
+    pub cache_config: Option<CacheConfig>,
+    #[serde(rename = "manager")]
+    pub manager_config: Option<ManagerConfig>,
+    #[serde(rename = "poller")]
+    pub poller_config: Option<PollerConfig>,
+    #[serde(rename = "sweep")]
+    pub sweep_config: Option<SweepConfig>,
+    #[serde(rename = "webhook")]
+    pub webhook_config: Option<WebhookConfig>,
+    #[serde(rename = "delivery_log")]
+    pub delivery_log_config: Option<DeliveryLogConfig>,
+    #[serde(rename = "admin")]
+    pub admin_config: Option<AdminConfig>,
+    #[serde(rename = "retry")]
+    pub retry_config: Option<RetryConfig>,
+    #[serde(rename = "circuit_breaker")]
+    pub circuit_breaker_config: Option<CircuitBreakerConfig>,
+    #[serde(rename = "shutdown")]
+    pub shutdown_config: Option<ShutdownConfig>,
+    #[serde(rename = "server")]
+    pub server_config: Option<ServerConfig>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -22,52 +46,265 @@ pub struct GithubConfig {
     pub owner: String,
     pub repository: String,
     pub api_token: String,
-    pub webhook_secret: String,
+    pub webhook_secret: Option<String>,
+    /// Additional accepted secrets, to allow rotating them without dropping deliveries.
+    #[serde(default)]
+    pub webhook_secrets: Vec<String>,
+    #[serde(default)]
+    pub use_jit_config: bool,
+    #[serde(default = "default_runner_group_id")]
+    pub runner_group_id: u64,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    /// Maximum number of runners for the repository.
+    pub max_runners: Option<usize>,
+    /// Queued jobs of repositories with a higher priority get a runner first.
+    #[serde(default)]
+    pub priority: i32,
     pub enabled: bool,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicRepositoryPolicy {
+    Allow,
+    Deny,
+    /// Only jobs triggered by a trusted sender are handled.
+    TrustedSenders,
+}
+
+/// Rules deciding whether a job may get a runner.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PolicyConfig {
+    #[serde(default = "default_public_repository_policy")]
+    pub public_repository: PublicRepositoryPolicy,
+    /// Whether jobs of a repository that is itself a fork are handled.
+    #[serde(default = "default_allow_fork_repositories")]
+    pub allow_fork_repositories: bool,
+    /// Whether jobs of workflow runs whose head comes from another repository, like pull requests from forks,
+    /// are handled. Checking it requires an API request per job.
+    #[serde(default = "default_allow_fork_pull_requests")]
+    pub allow_fork_pull_requests: bool,
+    /// When set, only jobs triggered by these logins are handled.
+    pub trusted_senders: Option<Vec<String>>,
+}
+
+fn default_public_repository_policy() -> PublicRepositoryPolicy {
+    PublicRepositoryPolicy::Allow
+}
+
+fn default_allow_fork_repositories() -> bool {
+    true
+}
+
+fn default_allow_fork_pull_requests() -> bool {
+    true
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        PolicyConfig {
+            public_repository: default_public_repository_policy(),
+            allow_fork_repositories: default_allow_fork_repositories(),
+            allow_fork_pull_requests: default_allow_fork_pull_requests(),
+            trusted_senders: None,
+        }
+    }
+}
+
+impl PolicyConfig {
+    pub fn is_trusted_sender(&self, login: &str) -> bool {
+        match &self.trusted_senders {
+            Some(trusted_senders) => trusted_senders
+                .iter()
+                .any(|trusted_sender| trusted_sender.eq_ignore_ascii_case(login)),
+            None => false,
+        }
+    }
+}
+
+fn default_runner_group_id() -> u64 {
+    // The "Default" runner group.
+    1
+}
+
 #[derive(Clone, Debug, Deserialize)]
 struct GithubTokenResponse {
     pub token: String,
     pub expires_at: String,
 }
 
+#[derive(Clone, Debug, Serialize)]
+struct GithubJitConfigRequest<'a> {
+    pub name: &'a str,
+    pub runner_group_id: u64,
+    pub labels: &'a [&'a str],
+    pub work_folder: &'a str,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GithubRunner {
+    pub id: u64,
+    pub name: String,
+    #[serde(default)]
+    pub status: String,
+    #[serde(default)]
+    pub busy: bool,
+    #[serde(default)]
+    pub labels: Vec<GithubRunnerLabel>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GithubRunnerLabel {
+    pub name: String,
+}
+
+impl GithubRunner {
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels
+            .iter()
+            .any(|runner_label| runner_label.name.eq_ignore_ascii_case(label))
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct GithubRunnerList {
+    pub total_count: u64,
+    pub runners: Vec<GithubRunner>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GithubJitConfig {
+    pub runner: GithubRunner,
+    pub encoded_jit_config: String,
+}
+
 impl GithubConfig {
-    pub fn get_webhook_secret_slice(&self) -> &[u8] {
-        self.webhook_secret.as_bytes()
+    pub fn get_webhook_secrets(&self) -> Vec<&str> {
+        self.webhook_secret
+            .iter()
+            .chain(self.webhook_secrets.iter())
+            .map(String::as_str)
+            .collect()
     }
 
     pub fn get_repo_url(&self) -> String {
         format!("https://github.com/{}/{}/", self.owner, self.repository)
     }
 
-    pub async fn request_new_repo_runner_token(&self) -> Option<String> {
-        let request_url = format!(
-            "https://api.github.com/repos/{}/{}/actions/runners/registration-token",
+    pub fn get_runner_name_prefix(&self) -> String {
+        format!("octoling-{}-{}-", self.owner, self.repository)
+    }
+
+    fn create_repo_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let mut request_url = format!(
+            "https://api.github.com/repos/{}/{}",
             self.owner, self.repository
         );
+
+        if !path.is_empty() {
+            request_url.push('/');
+            request_url.push_str(path);
+        }
+
         let authorization_value = format!("Token {}", self.api_token);
 
-        let response_result = reqwest::Client::new()
-            .post(request_url.as_str())
+        reqwest::Client::new()
+            .request(method, request_url.as_str())
             .header("Accept", "application/vnd.github.v3+json")
             .header("Authorization", authorization_value)
             .header("User-Agent", "octoling")
-            .send()
-            .await;
+    }
 
-        if let Ok(response) = response_result {
+    async fn send_repo_request<T: DeserializeOwned>(request: reqwest::RequestBuilder) -> Option<T> {
+        if let Ok(response) = request.send().await {
             if let Ok(response_text) = response.text().await {
-                if let Ok(token_response) =
-                    serde_json::from_str::<GithubTokenResponse>(&response_text)
-                {
-                    return Some(token_response.token);
+                if let Ok(response) = serde_json::from_str::<T>(&response_text) {
+                    return Some(response);
                 }
             }
         }
 
         None
     }
+
+    pub async fn get_repo_json<T: DeserializeOwned>(&self, path: &str) -> Option<T> {
+        Self::send_repo_request(self.create_repo_request(reqwest::Method::GET, path)).await
+    }
+
+    pub async fn request_new_repo_runner_token(&self) -> Option<String> {
+        let request =
+            self.create_repo_request(reqwest::Method::POST, "actions/runners/registration-token");
+
+        Self::send_repo_request::<GithubTokenResponse>(request)
+            .await
+            .map(|token_response| token_response.token)
+    }
+
+    // https://docs.github.com/en/rest/actions/self-hosted-runners#create-configuration-for-a-just-in-time-runner-for-a-repository
+    pub async fn request_new_repo_jit_config(
+        &self,
+        runner_name: &str,
+        labels: &[&str],
+    ) -> Option<GithubJitConfig> {
+        let request_body = serde_json::to_string(&GithubJitConfigRequest {
+            name: runner_name,
+            runner_group_id: self.runner_group_id,
+            labels,
+            work_folder: "_work",
+        })
+        .ok()?;
+        let request = self
+            .create_repo_request(reqwest::Method::POST, "actions/runners/generate-jitconfig")
+            .header("Content-Type", "application/json")
+            .body(request_body);
+
+        Self::send_repo_request(request).await
+    }
+
+    // https://docs.github.com/en/rest/actions/self-hosted-runners#list-self-hosted-runners-for-a-repository
+    pub async fn list_repo_runners(&self) -> Option<Vec<GithubRunner>> {
+        const PAGE_SIZE: usize = 100;
+
+        let mut runners = Vec::new();
+
+        for page in 1.. {
+            let request = self.create_repo_request(
+                reqwest::Method::GET,
+                format!("actions/runners?per_page={}&page={}", PAGE_SIZE, page).as_str(),
+            );
+            let runner_list = Self::send_repo_request::<GithubRunnerList>(request).await?;
+            let is_last_page = runner_list.runners.len() < PAGE_SIZE;
+
+            runners.extend(runner_list.runners);
+
+            if is_last_page {
+                break;
+            }
+        }
+
+        Some(runners)
+    }
+
+    pub async fn find_repo_runner_by_name(&self, runner_name: &str) -> Option<GithubRunner> {
+        self.list_repo_runners()
+            .await?
+            .into_iter()
+            .find(|runner| runner.name == runner_name)
+    }
+
+    // https://docs.github.com/en/rest/actions/self-hosted-runners#delete-a-self-hosted-runner-from-a-repository
+    pub async fn delete_repo_runner(&self, runner_id: u64) -> bool {
+        let request = self.create_repo_request(
+            reqwest::Method::DELETE,
+            format!("actions/runners/{}", runner_id).as_str(),
+        );
+
+        match request.send().await {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -76,6 +313,8 @@ pub struct ProviderConfig {
     pub id: String,
     #[serde(rename = "type")]
     pub provider_type: String,
+    /// Maximum number of runners created with the provider.
+    pub max_runners: Option<usize>,
     pub enabled: bool,
 }
 
@@ -83,21 +322,451 @@ pub struct ProviderConfig {
 pub struct ImageConfig {
     pub name: String,
     pub id: String,
+    #[serde(default)]
     pub provider_id: String,
+    /// Providers to fall back to, in priority order, when the previous ones cannot create a runner.
+    #[serde(default)]
+    pub provider_ids: Vec<String>,
     pub enabled: bool,
     pub labels: Vec<String>,
+    /// Repositories ("owner/repository") or organizations ("owner/*") allowed to use the image.
+    /// Every configured repository is allowed when unset.
+    pub allowed_repositories: Option<Vec<String>>,
+    /// Maximum number of runners created with the image.
+    pub max_runners: Option<usize>,
+    #[serde(default)]
+    pub resources: ResourcesConfig,
+    /// Duration in seconds after which a runner that didn't pick up a job is destroyed.
+    pub max_idle: Option<u64>,
+    /// Duration in seconds after which a runner is destroyed, even if it is running a job.
+    pub max_lifetime: Option<u64>,
 }
 
-static GLOBAL_CONFIG_PATH: Lazy<String> =
-    Lazy::new(|| env::var("CONFIG_FILE").unwrap_or_else(|_| String::from("octoling.toml")));
+/// Host resources a runner is expected to use, runners are only created when the host can fit them.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ResourcesConfig {
+    /// Memory in MiB.
+    pub memory: u64,
+    /// Number of CPUs kept busy.
+    pub cpus: f64,
+    /// Disk space in MiB.
+    pub disk: u64,
+}
 
-pub static GLOBAL_CONFIG: Lazy<Config> = Lazy::new(|| {
-    let mut file = File::open(GLOBAL_CONFIG_PATH.to_string()).unwrap();
-    let mut config_str = String::new();
+impl ResourcesConfig {
+    pub fn is_empty(&self) -> bool {
+        self.memory == 0 && self.cpus == 0.0 && self.disk == 0
+    }
+
+    pub fn add(&mut self, other: &ResourcesConfig) {
+        self.memory += other.memory;
+        self.cpus += other.cpus;
+        self.disk += other.disk;
+    }
+}
+
+impl ImageConfig {
+    /// Providers to create runners with, in priority order.
+    pub fn get_provider_ids(&self) -> Vec<&str> {
+        let mut provider_ids: Vec<&str> = Vec::new();
+
+        for provider_id in std::iter::once(&self.provider_id).chain(&self.provider_ids) {
+            if !provider_id.is_empty() && !provider_ids.contains(&provider_id.as_str()) {
+                provider_ids.push(provider_id.as_str());
+            }
+        }
+
+        provider_ids
+    }
+
+    /// Architecture label of the runners, taken from the "template:distribution:release:architecture" name.
+    pub fn get_architecture_label(&self) -> Option<&'static str> {
+        match self.name.rsplit(':').next()? {
+            "amd64" | "x86_64" => Some("X64"),
+            "arm64" | "aarch64" => Some("ARM64"),
+            "armhf" | "armel" | "armv7l" => Some("ARM"),
+            _ => None,
+        }
+    }
+
+    /// Labels are case insensitive, like on GitHub.
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels
+            .iter()
+            .any(|image_label| image_label.eq_ignore_ascii_case(label))
+    }
+
+    pub fn is_allowed_for_repository(&self, owner: &str, repository: &str) -> bool {
+        let allowed_repositories = match &self.allowed_repositories {
+            Some(allowed_repositories) => allowed_repositories,
+            None => return true,
+        };
+
+        // GitHub logins and repository names are case insensitive.
+        allowed_repositories.iter().any(|allowed_repository| {
+            match allowed_repository.split_once('/') {
+                Some((allowed_owner, "*")) => allowed_owner.eq_ignore_ascii_case(owner),
+                Some((allowed_owner, allowed_name)) => {
+                    allowed_owner.eq_ignore_ascii_case(owner)
+                        && allowed_name.eq_ignore_ascii_case(repository)
+                }
+                None => false,
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CacheConfig {
+    pub directory: String,
+    pub runner_sha256: Option<String>,
+    /// Duration in seconds after which files without a pinned checksum, like the Docker install script,
+    /// are downloaded again.
+    #[serde(default = "default_cache_max_age")]
+    pub max_age: u64,
+    pub enabled: bool,
+}
+
+fn default_cache_max_age() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ManagerConfig {
+    /// Interval in seconds between two reconciliations with GitHub.
+    #[serde(default = "default_reconcile_interval")]
+    pub reconcile_interval: u64,
+    /// Duration in seconds a runner must stay offline before the reconciliation deregisters it.
+    #[serde(default = "default_reconcile_grace_period")]
+    pub reconcile_grace_period: u64,
+    /// Identifies the runners created by this instance, defaults to the host name.
+    pub instance_id: Option<String>,
+    /// Maximum number of runners on this instance.
+    pub max_runners: Option<usize>,
+    /// Interval in seconds between two attempts to start the runners waiting for host resources.
+    #[serde(default = "default_dispatch_interval")]
+    pub dispatch_interval: u64,
+    /// Interval in seconds between two checks of the runner timeouts.
+    #[serde(default = "default_watchdog_interval")]
+    pub watchdog_interval: u64,
+}
+
+fn default_reconcile_interval() -> u64 {
+    300
+}
+
+fn default_reconcile_grace_period() -> u64 {
+    600
+}
+
+fn default_dispatch_interval() -> u64 {
+    10
+}
+
+fn default_watchdog_interval() -> u64 {
+    60
+}
+
+impl ManagerConfig {
+    pub fn get_instance_id(&self) -> String {
+        if let Some(instance_id) = &self.instance_id {
+            return instance_id.clone();
+        }
+
+        match std::fs::read_to_string("/proc/sys/kernel/hostname") {
+            Ok(hostname) if !hostname.trim().is_empty() => String::from(hostname.trim()),
+            _ => String::from("octoling"),
+        }
+    }
+}
+
+impl Default for ManagerConfig {
+    fn default() -> Self {
+        ManagerConfig {
+            reconcile_interval: default_reconcile_interval(),
+            reconcile_grace_period: default_reconcile_grace_period(),
+            instance_id: None,
+            max_runners: None,
+            dispatch_interval: default_dispatch_interval(),
+            watchdog_interval: default_watchdog_interval(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct PollerConfig {
+    /// Interval in seconds between two polls of the GitHub API.
+    pub interval: u64,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SweepConfig {
+    /// Interval in seconds between two sweeps for missed queued jobs.
+    pub interval: u64,
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WebhookConfig {
+    /// Duration in seconds during which delivery GUIDs and job IDs are remembered.
+    #[serde(default = "default_webhook_retention")]
+    pub retention: u64,
+    /// Maximum number of remembered delivery GUIDs.
+    #[serde(default = "default_webhook_max_retained_deliveries")]
+    pub max_retained_deliveries: usize,
+    /// Secrets accepted for any repository, used by organization webhooks.
+    #[serde(default)]
+    pub secrets: Vec<String>,
+}
+
+fn default_webhook_retention() -> u64 {
+    // GitHub cancels jobs that are queued for more than a day.
+    24 * 60 * 60
+}
+
+fn default_webhook_max_retained_deliveries() -> usize {
+    10000
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        WebhookConfig {
+            retention: default_webhook_retention(),
+            max_retained_deliveries: default_webhook_max_retained_deliveries(),
+            secrets: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RetryConfig {
+    /// Number of attempts of the setup steps that can safely be run again, like package installs and downloads.
+    #[serde(default = "default_retry_step_attempts")]
+    pub step_attempts: u32,
+    /// Delay in seconds between two attempts of a setup step.
+    #[serde(default = "default_retry_step_delay")]
+    pub step_delay: u64,
+    /// Number of attempts to provision a runner from scratch.
+    #[serde(default = "default_retry_runner_attempts")]
+    pub runner_attempts: u32,
+    /// Delay in seconds before provisioning a runner again, doubled after every failed attempt.
+    #[serde(default = "default_retry_initial_backoff")]
+    pub initial_backoff: u64,
+    /// Maximum delay in seconds before provisioning a runner again.
+    #[serde(default = "default_retry_max_backoff")]
+    pub max_backoff: u64,
+}
+
+fn default_retry_step_attempts() -> u32 {
+    3
+}
+
+fn default_retry_step_delay() -> u64 {
+    5
+}
+
+fn default_retry_runner_attempts() -> u32 {
+    3
+}
+
+fn default_retry_initial_backoff() -> u64 {
+    30
+}
+
+fn default_retry_max_backoff() -> u64 {
+    600
+}
+
+impl RetryConfig {
+    /// Delay before the given provisioning attempt, starting at 1 for the first retry.
+    pub fn get_backoff(&self, retry: u32) -> Duration {
+        let multiplier = 1u64
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u64::MAX);
+        let backoff = self.initial_backoff.saturating_mul(multiplier);
+
+        Duration::from_secs(backoff.min(self.max_backoff))
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            step_attempts: default_retry_step_attempts(),
+            step_delay: default_retry_step_delay(),
+            runner_attempts: default_retry_runner_attempts(),
+            initial_backoff: default_retry_initial_backoff(),
+            max_backoff: default_retry_max_backoff(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Number of consecutive failures after which an image is not used anymore on a provider.
+    #[serde(default = "default_circuit_breaker_failure_threshold")]
+    pub failure_threshold: u32,
+    /// Duration in seconds before a probe runner checks if the image works again.
+    #[serde(default = "default_circuit_breaker_cooldown")]
+    pub cooldown: u64,
+    #[serde(default = "default_circuit_breaker_enabled")]
+    pub enabled: bool,
+}
+
+fn default_circuit_breaker_failure_threshold() -> u32 {
+    3
+}
+
+fn default_circuit_breaker_cooldown() -> u64 {
+    600
+}
+
+fn default_circuit_breaker_enabled() -> bool {
+    true
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            failure_threshold: default_circuit_breaker_failure_threshold(),
+            cooldown: default_circuit_breaker_cooldown(),
+            enabled: default_circuit_breaker_enabled(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DeliveryLogConfig {
+    pub directory: String,
+    /// Size in bytes after which the log file is rotated.
+    #[serde(default = "default_delivery_log_max_file_size")]
+    pub max_file_size: u64,
+    /// Number of rotated log files to keep.
+    #[serde(default = "default_delivery_log_max_files")]
+    pub max_files: usize,
+    pub enabled: bool,
+}
+
+fn default_delivery_log_max_file_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_delivery_log_max_files() -> usize {
+    10
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServerConfig {
+    /// Addresses the server listens on.
+    #[serde(default = "default_server_addresses")]
+    pub addresses: Vec<IpAddr>,
+    #[serde(default = "default_server_port")]
+    pub port: u16,
+    pub tls: Option<TlsConfig>,
+    /// Unix socket serving the admin API, which isn't served over TCP anymore when set.
+    pub admin_socket: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TlsConfig {
+    /// PEM encoded certificate chain.
+    #[serde(default)]
+    pub cert_path: String,
+    /// PEM encoded private key.
+    #[serde(default)]
+    pub key_path: String,
+}
+
+fn default_server_addresses() -> Vec<IpAddr> {
+    vec![IpAddr::from([127, 0, 0, 1])]
+}
+
+fn default_server_port() -> u16 {
+    8000
+}
+
+impl ServerConfig {
+    pub fn get_socket_addresses(&self) -> Vec<SocketAddr> {
+        self.addresses
+            .iter()
+            .map(|address| SocketAddr::new(*address, self.port))
+            .collect()
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            addresses: default_server_addresses(),
+            port: default_server_port(),
+            tls: None,
+            admin_socket: None,
+        }
+    }
+}
+
+/// What happens to the runners still alive when octoling shuts down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShutdownRunnerPolicy {
+    /// Wait for the runners to finish their job, up to the shutdown timeout.
+    Wait,
+    Destroy,
+    /// Leave the runners running, they aren't tracked after a restart and must be destroyed manually
+    /// with `octoling destroy`.
+    Keep,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ShutdownConfig {
+    #[serde(default = "default_shutdown_runners")]
+    pub runners: ShutdownRunnerPolicy,
+    /// Duration in seconds after which the runners still alive are destroyed.
+    #[serde(default = "default_shutdown_timeout")]
+    pub timeout: u64,
+}
+
+fn default_shutdown_runners() -> ShutdownRunnerPolicy {
+    ShutdownRunnerPolicy::Wait
+}
+
+fn default_shutdown_timeout() -> u64 {
+    3600
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            runners: default_shutdown_runners(),
+            timeout: default_shutdown_timeout(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AdminConfig {
+    /// Bearer token required by the admin API.
+    pub token: String,
+}
 
-    file.read_to_string(&mut config_str).unwrap();
+// Set from the command line, takes precedence over the environment.
+static GLOBAL_CONFIG_PATH_OVERRIDE: OnceCell<String> = OnceCell::new();
 
-    toml::from_str(config_str.as_str()).unwrap()
+static GLOBAL_CONFIG_PATH: Lazy<String> = Lazy::new(|| match GLOBAL_CONFIG_PATH_OVERRIDE.get() {
+    Some(path) => path.clone(),
+    None => env::var("CONFIG_FILE").unwrap_or_else(|_| String::from("octoling.toml")),
+});
+
+// Set by load() once the configuration was read and validated.
+static GLOBAL_LOADED_CONFIG: OnceCell<Config> = OnceCell::new();
+
+pub static GLOBAL_CONFIG: Lazy<Config> = Lazy::new(|| {
+    GLOBAL_LOADED_CONFIG
+        .get()
+        .cloned()
+        .expect("the configuration must be loaded first")
 });
 
 pub static GLOBAL_GITHUB_CONFIG: Lazy<Vec<GithubConfig>> =
@@ -118,14 +787,313 @@ pub static GLOBAL_IMAGE_CONFIG: Lazy<Vec<ImageConfig>> =
         None => Vec::new(),
     });
 
-pub fn load() {
-    Lazy::force(&GLOBAL_CONFIG_PATH);
-    Lazy::force(&GLOBAL_CONFIG);
+pub static GLOBAL_CACHE_CONFIG: Lazy<Option<CacheConfig>> =
+    Lazy::new(|| GLOBAL_CONFIG.cache_config.clone());
+
+pub static GLOBAL_MANAGER_CONFIG: Lazy<ManagerConfig> =
+    Lazy::new(|| GLOBAL_CONFIG.manager_config.clone().unwrap_or_default());
+
+pub static GLOBAL_RETRY_CONFIG: Lazy<RetryConfig> =
+    Lazy::new(|| GLOBAL_CONFIG.retry_config.clone().unwrap_or_default());
+
+pub static GLOBAL_CIRCUIT_BREAKER_CONFIG: Lazy<CircuitBreakerConfig> = Lazy::new(|| {
+    GLOBAL_CONFIG
+        .circuit_breaker_config
+        .clone()
+        .unwrap_or_default()
+});
+
+pub static GLOBAL_POLLER_CONFIG: Lazy<Option<PollerConfig>> =
+    Lazy::new(|| GLOBAL_CONFIG.poller_config.clone());
+
+pub static GLOBAL_SWEEP_CONFIG: Lazy<Option<SweepConfig>> =
+    Lazy::new(|| GLOBAL_CONFIG.sweep_config.clone());
+
+pub static GLOBAL_WEBHOOK_CONFIG: Lazy<WebhookConfig> =
+    Lazy::new(|| GLOBAL_CONFIG.webhook_config.clone().unwrap_or_default());
+
+pub static GLOBAL_DELIVERY_LOG_CONFIG: Lazy<Option<DeliveryLogConfig>> =
+    Lazy::new(|| GLOBAL_CONFIG.delivery_log_config.clone());
+
+pub static GLOBAL_SHUTDOWN_CONFIG: Lazy<ShutdownConfig> =
+    Lazy::new(|| GLOBAL_CONFIG.shutdown_config.clone().unwrap_or_default());
+
+pub static GLOBAL_SERVER_CONFIG: Lazy<ServerConfig> =
+    Lazy::new(|| GLOBAL_CONFIG.server_config.clone().unwrap_or_default());
+
+pub static GLOBAL_ADMIN_CONFIG: Lazy<Option<AdminConfig>> =
+    Lazy::new(|| GLOBAL_CONFIG.admin_config.clone());
+
+/// Use another configuration file, must be called before the configuration is loaded.
+pub fn set_path(path: &str) {
+    let _ = GLOBAL_CONFIG_PATH_OVERRIDE.set(String::from(path));
+}
+
+fn read(path: &str) -> Result<Config, String> {
+    let mut file = File::open(path).map_err(|error| format!("{}: {}", path, error))?;
+    let mut config_str = String::new();
+
+    file.read_to_string(&mut config_str)
+        .map_err(|error| format!("{}: {}", path, error))?;
+
+    toml::from_str(config_str.as_str()).map_err(|error| format!("{}: {}", path, error))
+}
+
+fn describe_provider(index: usize, provider_config: &ProviderConfig) -> String {
+    format!("provider #{} (id \"{}\")", index + 1, provider_config.id)
+}
+
+fn describe_image(index: usize, image_config: &ImageConfig) -> String {
+    format!("image #{} (id \"{}\")", index + 1, image_config.id)
+}
+
+/// Check the consistency of a configuration, return a description of every problem found.
+pub fn validate(config: &Config) -> Vec<String> {
+    let mut errors = Vec::new();
+    let github_configs = config.github_configs.as_deref().unwrap_or_default();
+    let provider_configs = config.provider_configs.as_deref().unwrap_or_default();
+    let image_configs = config.image_configs.as_deref().unwrap_or_default();
+
+    for (index, github_config) in github_configs.iter().enumerate() {
+        let is_duplicate = github_configs[..index].iter().any(|other| {
+            other
+                .owner
+                .eq_ignore_ascii_case(github_config.owner.as_str())
+                && other
+                    .repository
+                    .eq_ignore_ascii_case(github_config.repository.as_str())
+        });
+
+        if is_duplicate {
+            errors.push(format!(
+                "github #{}: repository {}/{} is configured more than once",
+                index + 1,
+                github_config.owner,
+                github_config.repository
+            ));
+        }
+    }
+
+    for (index, provider_config) in provider_configs.iter().enumerate() {
+        let location = describe_provider(index, provider_config);
+
+        if provider_config.id.is_empty() {
+            errors.push(format!("{}: id is empty", location));
+        } else if provider_configs[..index]
+            .iter()
+            .any(|other| other.id == provider_config.id)
+        {
+            errors.push(format!("{}: id is used by another provider", location));
+        }
+
+        if !crate::provider::is_supported_type(provider_config.provider_type.as_str()) {
+            errors.push(format!(
+                "{}: unknown or unsupported type \"{}\"",
+                location, provider_config.provider_type
+            ));
+        }
+    }
+
+    for (index, image_config) in image_configs.iter().enumerate() {
+        let location = describe_image(index, image_config);
+
+        if image_config.id.is_empty() {
+            errors.push(format!("{}: id is empty", location));
+        } else if image_configs[..index]
+            .iter()
+            .any(|other| other.id == image_config.id)
+        {
+            errors.push(format!("{}: id is used by another image", location));
+        }
+
+        let provider_ids = image_config.get_provider_ids();
+
+        if provider_ids.is_empty() {
+            errors.push(format!(
+                "{}: no provider_id or provider_ids is set",
+                location
+            ));
+        }
+
+        for provider_id in provider_ids {
+            match provider_configs
+                .iter()
+                .find(|provider_config| provider_config.id == provider_id)
+            {
+                Some(provider_config) => {
+                    // LXC images are "template:distribution:release:architecture".
+                    let is_valid_name = provider_config.provider_type != "lxc"
+                        || image_config
+                            .name
+                            .split(':')
+                            .filter(|part| !part.is_empty())
+                            .count()
+                            == 4;
+
+                    if !is_valid_name {
+                        errors.push(format!(
+                            "{}: invalid name \"{}\" for provider {}, expected \"template:distribution:release:architecture\"",
+                            location, image_config.name, provider_id
+                        ));
+                    }
+                }
+                None => errors.push(format!(
+                    "{}: provider \"{}\" does not exist",
+                    location, provider_id
+                )),
+            }
+        }
+
+        if image_config.name.is_empty() {
+            errors.push(format!("{}: name is empty", location));
+        }
+
+        let labels: Vec<String> = image_config
+            .labels
+            .iter()
+            .filter(|label| !is_builtin_label(label.as_str()))
+            .map(|label| label.to_lowercase())
+            .collect();
+
+        if labels.is_empty() {
+            errors.push(format!(
+                "{}: labels must contain at least one label that isn't builtin ({})",
+                location,
+                BUILTIN_LABELS.join(", ")
+            ));
+        }
+
+        for (label_index, label) in labels.iter().enumerate() {
+            if labels[..label_index].contains(label) {
+                errors.push(format!("{}: label \"{}\" is listed twice", location, label));
+            }
+        }
+
+        if let Some(allowed_repositories) = &image_config.allowed_repositories {
+            for allowed_repository in allowed_repositories {
+                let is_valid = matches!(
+                    allowed_repository.split_once('/'),
+                    Some((owner, repository)) if !owner.is_empty() && !repository.is_empty() && !repository.contains('/')
+                );
+
+                if !is_valid {
+                    errors.push(format!(
+                        "{}: invalid allowed repository \"{}\", expected \"owner/repository\" or \"owner/*\"",
+                        location, allowed_repository
+                    ));
+                }
+            }
+        }
+
+        // Jobs only asking for labels shared by two images would get a runner of each image.
+        if image_config.enabled {
+            for (other_index, other) in image_configs[..index].iter().enumerate() {
+                if !other.enabled {
+                    continue;
+                }
+
+                let mut shared_labels: Vec<&str> = labels
+                    .iter()
+                    .filter(|label| other.has_label(label.as_str()))
+                    .map(String::as_str)
+                    .collect();
+
+                shared_labels.sort_unstable();
+                shared_labels.dedup();
+
+                if !shared_labels.is_empty() {
+                    errors.push(format!(
+                        "{}: shares the labels \"{}\" with {}, jobs asking only for them would match both images",
+                        location,
+                        shared_labels.join("\", \""),
+                        describe_image(other_index, other)
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(manager_config) = &config.manager_config {
+        for (name, interval) in [
+            ("reconcile_interval", manager_config.reconcile_interval),
+            ("dispatch_interval", manager_config.dispatch_interval),
+            ("watchdog_interval", manager_config.watchdog_interval),
+        ] {
+            if interval == 0 {
+                errors.push(format!("manager: {} must be greater than 0", name));
+            }
+        }
+    }
+
+    if let Some(poller_config) = &config.poller_config {
+        if poller_config.enabled && poller_config.interval == 0 {
+            errors.push(String::from("poller: interval must be greater than 0"));
+        }
+    }
+
+    if let Some(sweep_config) = &config.sweep_config {
+        if sweep_config.enabled && sweep_config.interval == 0 {
+            errors.push(String::from("sweep: interval must be greater than 0"));
+        }
+    }
+
+    if let Some(tls_config) = config
+        .server_config
+        .as_ref()
+        .and_then(|server_config| server_config.tls.as_ref())
+    {
+        if tls_config.cert_path.is_empty() {
+            errors.push(String::from("server.tls: cert_path is not set"));
+        }
+
+        if tls_config.key_path.is_empty() {
+            errors.push(String::from("server.tls: key_path is not set"));
+        }
+    }
+
+    errors
+}
+
+/// Read and validate the configuration file, return every problem found on failure.
+pub fn load() -> Result<(), Vec<String>> {
+    if GLOBAL_LOADED_CONFIG.get().is_some() {
+        return Ok(());
+    }
+
+    let config = read(GLOBAL_CONFIG_PATH.as_str()).map_err(|error| vec![error])?;
+    let errors = validate(&config);
+
+    if !errors.is_empty() {
+        return Err(errors
+            .into_iter()
+            .map(|error| format!("{}: {}", GLOBAL_CONFIG_PATH.as_str(), error))
+            .collect());
+    }
+
+    let _ = GLOBAL_LOADED_CONFIG.set(config);
+
+    Ok(())
+}
+
+pub fn get_image_config_by_id(id: &str) -> Option<ImageConfig> {
+    GLOBAL_IMAGE_CONFIG
+        .iter()
+        .find(|image_config| image_config.id.as_str() == id)
+        .cloned()
+}
+
+pub fn get_provider_config_by_id(id: &str) -> Option<ProviderConfig> {
+    GLOBAL_PROVIDER_CONFIG
+        .iter()
+        .find(|provider_config| provider_config.id.as_str() == id)
+        .cloned()
 }
 
+/// Owners and repositories are case insensitive, like on GitHub.
 pub fn get_github_config_by_owner_and_repo(owner: &str, repository: &str) -> Option<GithubConfig> {
     for github_config in &*GLOBAL_GITHUB_CONFIG {
-        if github_config.owner.as_str() == owner && github_config.repository.as_str() == repository
+        if github_config.owner.eq_ignore_ascii_case(owner)
+            && github_config.repository.eq_ignore_ascii_case(repository)
         {
             return Some(github_config.clone());
         }
@@ -134,14 +1102,429 @@ pub fn get_github_config_by_owner_and_repo(owner: &str, repository: &str) -> Opt
     None
 }
 
-pub fn get_image_config_by_label(label: &str) -> Option<ImageConfig> {
-    for image_config in &*GLOBAL_IMAGE_CONFIG {
-        for image_config_label in &image_config.labels {
-            if image_config_label == label {
-                return Some(image_config.clone());
-            }
-        }
+// Labels every runner has, they do not tell anything about the image to use.
+const BUILTIN_LABELS: [&str; 8] = [
+    "self-hosted",
+    "linux",
+    "windows",
+    "macos",
+    "x64",
+    "arm",
+    "arm64",
+    "octoling",
+];
+
+fn is_builtin_label(label: &str) -> bool {
+    BUILTIN_LABELS
+        .iter()
+        .any(|builtin_label| builtin_label.eq_ignore_ascii_case(label))
+}
+
+/// Get every enabled image carrying all the labels requested by a job.
+pub fn get_image_configs_by_labels(labels: &[String]) -> Vec<ImageConfig> {
+    find_image_configs_by_labels(&GLOBAL_IMAGE_CONFIG, labels)
+}
+
+fn find_image_configs_by_labels(
+    image_configs: &[ImageConfig],
+    labels: &[String],
+) -> Vec<ImageConfig> {
+    let requested_labels: Vec<&String> = labels
+        .iter()
+        .filter(|label| !is_builtin_label(label.as_str()))
+        .collect();
+
+    // Jobs only asking for builtin labels could run on any self-hosted runner.
+    if requested_labels.is_empty() {
+        return Vec::new();
     }
 
-    None
+    image_configs
+        .iter()
+        .filter(|image_config| {
+            image_config.enabled
+                && requested_labels
+                    .iter()
+                    .all(|label| image_config.has_label(label.as_str()))
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROVIDER: &str = r#"
+        [[provider]]
+        name = "LXC local"
+        id = "lxc"
+        type = "lxc"
+        enabled = true
+    "#;
+
+    const IMAGE: &str = r#"
+        [[image]]
+        name = "download:ubuntu:focal:amd64"
+        id = "focal"
+        provider_id = "lxc"
+        labels = ["self-hosted", "octoling-focal"]
+        enabled = true
+    "#;
+
+    fn validate_str(config: &str) -> Vec<String> {
+        validate(&toml::from_str(config).unwrap())
+    }
+
+    fn assert_error(config: &str, expected_error: &str) {
+        let errors = validate_str(config);
+
+        assert!(
+            errors.iter().any(|error| error.contains(expected_error)),
+            "expected \"{}\" in {:?}",
+            expected_error,
+            errors
+        );
+    }
+
+    #[test]
+    fn valid_config_has_no_error() {
+        assert_eq!(
+            validate_str(&format!("{}{}", PROVIDER, IMAGE)),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn example_config_is_valid() {
+        assert_eq!(
+            validate_str(include_str!("../octoling.toml.example")),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn duplicate_repository_is_reported() {
+        let github = r#"
+            [[github]]
+            owner = "octo-org"
+            repository = "hello-world"
+            api_token = "token"
+            enabled = true
+        "#;
+
+        assert_error(
+            &format!("{}{}", github, github),
+            "github #2: repository octo-org/hello-world is configured more than once",
+        );
+    }
+
+    #[test]
+    fn invalid_providers_are_reported() {
+        let config = r#"
+            [[provider]]
+            name = "Empty"
+            id = ""
+            type = "lxc"
+            enabled = true
+
+            [[provider]]
+            name = "First"
+            id = "lxc"
+            type = "lxc"
+            enabled = true
+
+            [[provider]]
+            name = "Second"
+            id = "lxc"
+            type = "unknown"
+            enabled = true
+        "#;
+
+        assert_error(config, "provider #1 (id \"\"): id is empty");
+        assert_error(
+            config,
+            "provider #3 (id \"lxc\"): id is used by another provider",
+        );
+        assert_error(
+            config,
+            "provider #3 (id \"lxc\"): unknown or unsupported type \"unknown\"",
+        );
+    }
+
+    #[test]
+    fn image_ids_and_names_are_checked() {
+        let config = format!(
+            "{}{}{}",
+            PROVIDER,
+            IMAGE,
+            r#"
+            [[image]]
+            name = ""
+            id = "focal"
+            provider_id = "lxc"
+            labels = ["octoling-other"]
+            enabled = true
+
+            [[image]]
+            name = "ubuntu"
+            id = ""
+            provider_id = "lxc"
+            labels = ["octoling-ubuntu"]
+            enabled = true
+            "#
+        );
+
+        assert_error(
+            &config,
+            "image #2 (id \"focal\"): id is used by another image",
+        );
+        assert_error(&config, "image #2 (id \"focal\"): name is empty");
+        assert_error(&config, "image #3 (id \"\"): id is empty");
+        assert_error(
+            &config,
+            "image #3 (id \"\"): invalid name \"ubuntu\" for provider lxc",
+        );
+    }
+
+    #[test]
+    fn image_providers_are_checked() {
+        let config = format!(
+            "{}{}",
+            PROVIDER,
+            r#"
+            [[image]]
+            name = "download:ubuntu:focal:amd64"
+            id = "none"
+            labels = ["octoling-none"]
+            enabled = true
+
+            [[image]]
+            name = "download:ubuntu:focal:amd64"
+            id = "missing"
+            provider_ids = ["lxc", "missing"]
+            labels = ["octoling-missing"]
+            enabled = true
+            "#
+        );
+
+        assert_error(
+            &config,
+            "image #1 (id \"none\"): no provider_id or provider_ids is set",
+        );
+        assert_error(
+            &config,
+            "image #2 (id \"missing\"): provider \"missing\" does not exist",
+        );
+    }
+
+    #[test]
+    fn image_labels_are_checked() {
+        let config = format!(
+            "{}{}",
+            PROVIDER,
+            r#"
+            [[image]]
+            name = "download:ubuntu:focal:amd64"
+            id = "builtin"
+            provider_id = "lxc"
+            labels = ["self-hosted", "Linux"]
+            enabled = true
+
+            [[image]]
+            name = "download:ubuntu:focal:amd64"
+            id = "twice"
+            provider_id = "lxc"
+            labels = ["octoling-twice", "Octoling-Twice"]
+            enabled = true
+            "#
+        );
+
+        assert_error(
+            &config,
+            "image #1 (id \"builtin\"): labels must contain at least one label that isn't builtin",
+        );
+        assert_error(
+            &config,
+            "image #2 (id \"twice\"): label \"octoling-twice\" is listed twice",
+        );
+    }
+
+    #[test]
+    fn invalid_allowed_repository_is_reported() {
+        let config = format!(
+            "{}{}",
+            PROVIDER,
+            IMAGE.replace("enabled", "allowed_repositories = [\"octo-org\"]\nenabled")
+        );
+
+        assert_error(
+            &config,
+            "image #1 (id \"focal\"): invalid allowed repository \"octo-org\"",
+        );
+    }
+
+    #[test]
+    fn shared_labels_are_reported() {
+        let other_image = r#"
+            [[image]]
+            name = "download:ubuntu:jammy:amd64"
+            id = "jammy"
+            provider_id = "lxc"
+            labels = ["self-hosted", "Octoling-Focal", "octoling-jammy"]
+            enabled = true
+        "#;
+
+        assert_error(
+            &format!("{}{}{}", PROVIDER, IMAGE, other_image),
+            "image #2 (id \"jammy\"): shares the labels \"octoling-focal\" with image #1 (id \"focal\")",
+        );
+
+        // Disabled images don't get jobs.
+        assert_eq!(
+            validate_str(&format!(
+                "{}{}{}",
+                PROVIDER,
+                IMAGE,
+                other_image.replace("enabled = true", "enabled = false")
+            )),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn architecture_label_comes_from_the_image_name() {
+        let mut image_config: ImageConfig = toml::from_str::<Config>(IMAGE)
+            .unwrap()
+            .image_configs
+            .unwrap()
+            .remove(0);
+
+        assert_eq!(image_config.get_architecture_label(), Some("X64"));
+
+        image_config.name = String::from("download:ubuntu:focal:arm64");
+        assert_eq!(image_config.get_architecture_label(), Some("ARM64"));
+
+        image_config.name = String::from("download:ubuntu:focal:riscv64");
+        assert_eq!(image_config.get_architecture_label(), None);
+    }
+
+    fn find_image_ids(image_configs: &[ImageConfig], labels: &[&str]) -> Vec<String> {
+        let labels: Vec<String> = labels.iter().map(|label| String::from(*label)).collect();
+
+        find_image_configs_by_labels(image_configs, &labels)
+            .into_iter()
+            .map(|image_config| image_config.id)
+            .collect()
+    }
+
+    fn image_configs() -> Vec<ImageConfig> {
+        let config: Config = toml::from_str(&format!(
+            "{}{}",
+            IMAGE,
+            r#"
+            [[image]]
+            name = "download:ubuntu:jammy:amd64"
+            id = "jammy"
+            provider_id = "lxc"
+            labels = ["octoling-jammy", "Docker"]
+            enabled = true
+
+            [[image]]
+            name = "download:ubuntu:noble:amd64"
+            id = "noble"
+            provider_id = "lxc"
+            labels = ["octoling-noble"]
+            enabled = false
+            "#
+        ))
+        .unwrap();
+
+        config.image_configs.unwrap()
+    }
+
+    #[test]
+    fn images_carrying_every_requested_label_match() {
+        let image_configs = image_configs();
+
+        assert_eq!(
+            find_image_ids(&image_configs, &["octoling-jammy"]),
+            ["jammy"]
+        );
+        assert_eq!(
+            find_image_ids(&image_configs, &["octoling-jammy", "docker"]),
+            ["jammy"]
+        );
+        assert!(find_image_ids(&image_configs, &["octoling-focal", "docker"]).is_empty());
+    }
+
+    #[test]
+    fn builtin_labels_are_ignored() {
+        let image_configs = image_configs();
+
+        assert_eq!(
+            find_image_ids(
+                &image_configs,
+                &["self-hosted", "Linux", "X64", "octoling-jammy"]
+            ),
+            ["jammy"]
+        );
+        assert!(find_image_ids(&image_configs, &["self-hosted", "linux", "x64"]).is_empty());
+        assert!(find_image_ids(&image_configs, &[]).is_empty());
+    }
+
+    #[test]
+    fn disabled_images_are_skipped() {
+        assert!(find_image_ids(&image_configs(), &["octoling-noble"]).is_empty());
+    }
+
+    #[test]
+    fn labels_match_case_insensitively() {
+        let image_configs = image_configs();
+
+        assert_eq!(
+            find_image_ids(&image_configs, &["Octoling-Focal"]),
+            ["focal"]
+        );
+        assert_eq!(
+            find_image_ids(&image_configs, &["OCTOLING-JAMMY", "DOCKER"]),
+            ["jammy"]
+        );
+    }
+
+    #[test]
+    fn zero_intervals_are_reported() {
+        let config = r#"
+            [manager]
+            reconcile_interval = 0
+            dispatch_interval = 0
+            watchdog_interval = 0
+
+            [poller]
+            interval = 0
+            enabled = true
+
+            [sweep]
+            interval = 0
+            enabled = true
+        "#;
+
+        assert_error(config, "manager: reconcile_interval must be greater than 0");
+        assert_error(config, "manager: dispatch_interval must be greater than 0");
+        assert_error(config, "manager: watchdog_interval must be greater than 0");
+        assert_error(config, "poller: interval must be greater than 0");
+        assert_error(config, "sweep: interval must be greater than 0");
+    }
+
+    #[test]
+    fn incomplete_tls_is_reported() {
+        assert_error(
+            "[server.tls]\ncert_path = \"/etc/octoling/cert.pem\"",
+            "server.tls: key_path is not set",
+        );
+        assert_error(
+            "[server.tls]\nkey_path = \"/etc/octoling/key.pem\"",
+            "server.tls: cert_path is not set",
+        );
+    }
 }
@@ -0,0 +1,146 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::{DeliveryLogConfig, GLOBAL_DELIVERY_LOG_CONFIG};
+
+const LOG_FILE_NAME: &str = "deliveries.log";
+
+/// A verified webhook delivery as received from GitHub.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StoredDelivery {
+    pub delivery: Option<String>,
+    pub event: String,
+    /// Reception time in seconds since the UNIX epoch.
+    pub received_at: u64,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl StoredDelivery {
+    pub fn new(
+        delivery: Option<&str>,
+        event: &str,
+        headers: BTreeMap<String, String>,
+        body: &str,
+    ) -> Self {
+        let received_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        StoredDelivery {
+            delivery: delivery.map(String::from),
+            event: String::from(event),
+            received_at,
+            headers,
+            body: String::from(body),
+        }
+    }
+}
+
+// Serialize writes and rotations.
+static GLOBAL_DELIVERY_LOG_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn get_enabled_config() -> Option<&'static DeliveryLogConfig> {
+    match &*GLOBAL_DELIVERY_LOG_CONFIG {
+        Some(delivery_log_config) if delivery_log_config.enabled => Some(delivery_log_config),
+        _ => None,
+    }
+}
+
+/// Path of the log file with the given rotation index, 0 being the file currently written.
+fn get_log_path(directory: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        directory.join(LOG_FILE_NAME)
+    } else {
+        directory.join(format!("{}.{}", LOG_FILE_NAME, index))
+    }
+}
+
+fn rotate(delivery_log_config: &DeliveryLogConfig) -> std::io::Result<()> {
+    let directory = Path::new(delivery_log_config.directory.as_str());
+
+    let _ = fs::remove_file(get_log_path(directory, delivery_log_config.max_files));
+
+    for index in (0..delivery_log_config.max_files).rev() {
+        let path = get_log_path(directory, index);
+
+        if path.exists() {
+            fs::rename(path, get_log_path(directory, index + 1))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn append(delivery_log_config: &DeliveryLogConfig, line: &str) -> std::io::Result<()> {
+    let directory = Path::new(delivery_log_config.directory.as_str());
+    let path = get_log_path(directory, 0);
+
+    fs::create_dir_all(directory)?;
+
+    if let Ok(metadata) = fs::metadata(&path) {
+        if metadata.len() + line.len() as u64 > delivery_log_config.max_file_size {
+            rotate(delivery_log_config)?;
+        }
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    file.write_all(line.as_bytes())?;
+    file.write_all(b"\n")
+}
+
+/// Persist a delivery if the delivery log is enabled.
+pub fn record(stored_delivery: &StoredDelivery) {
+    let delivery_log_config = match get_enabled_config() {
+        Some(delivery_log_config) => delivery_log_config,
+        None => return,
+    };
+
+    let line = match serde_json::to_string(stored_delivery) {
+        Ok(line) => line,
+        Err(_) => return,
+    };
+
+    let _lock = GLOBAL_DELIVERY_LOG_LOCK.lock().unwrap();
+
+    if let Err(error) = append(delivery_log_config, line.as_str()) {
+        eprintln!("octoling: Cannot record delivery: {}", error);
+    }
+}
+
+/// Find the most recent record of a delivery.
+pub fn find(delivery: &str) -> Option<StoredDelivery> {
+    let delivery_log_config = get_enabled_config()?;
+    let directory = Path::new(delivery_log_config.directory.as_str());
+
+    let _lock = GLOBAL_DELIVERY_LOG_LOCK.lock().unwrap();
+
+    // Newest files first.
+    for index in 0..=delivery_log_config.max_files {
+        let file = match File::open(get_log_path(directory, index)) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+
+        let found_delivery = BufReader::new(file)
+            .lines()
+            .map_while(|line| line.ok())
+            .filter_map(|line| serde_json::from_str::<StoredDelivery>(line.as_str()).ok())
+            .filter(|stored_delivery| stored_delivery.delivery.as_deref() == Some(delivery))
+            .last();
+
+        if found_delivery.is_some() {
+            return found_delivery;
+        }
+    }
+
+    None
+}
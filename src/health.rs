@@ -0,0 +1,196 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::{self, ImageConfig, GLOBAL_CIRCUIT_BREAKER_CONFIG};
+use crate::manager;
+use crate::scheduler;
+
+const PROBE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug, Default)]
+struct Health {
+    consecutive_failures: u32,
+    /// Set when the failure threshold was reached, runners aren't created until a probe succeeds.
+    unhealthy_since: Option<Instant>,
+}
+
+/// Health of an image on a provider, as exposed by the API.
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthStatus {
+    pub image_id: String,
+    pub provider_id: String,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    /// Seconds since the image was marked unhealthy or last failed a probe.
+    pub unhealthy_for: Option<u64>,
+}
+
+// Keeps the names of probe runners created during the same second unique.
+static GLOBAL_PROBE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Keyed by image and provider IDs.
+static GLOBAL_HEALTH: Lazy<Mutex<HashMap<(String, String), Health>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn get_key(image_id: &str, provider_id: &str) -> (String, String) {
+    (String::from(image_id), String::from(provider_id))
+}
+
+pub fn is_healthy(image_id: &str, provider_id: &str) -> bool {
+    GLOBAL_HEALTH
+        .lock()
+        .unwrap()
+        .get(&get_key(image_id, provider_id))
+        .and_then(|health| health.unhealthy_since)
+        .is_none()
+}
+
+pub fn record_success(image_id: &str, provider_id: &str) {
+    let previous_health = GLOBAL_HEALTH
+        .lock()
+        .unwrap()
+        .remove(&get_key(image_id, provider_id));
+
+    if let Some(Health {
+        unhealthy_since: Some(_),
+        ..
+    }) = previous_health
+    {
+        info!(
+            "octoling: Image {} on provider {} is healthy again",
+            image_id, provider_id
+        );
+
+        // Requests may have been waiting for the image.
+        scheduler::dispatch();
+    }
+}
+
+pub fn record_failure(image_id: &str, provider_id: &str) {
+    let circuit_breaker_config = &*GLOBAL_CIRCUIT_BREAKER_CONFIG;
+
+    if !circuit_breaker_config.enabled {
+        return;
+    }
+
+    let mut health = GLOBAL_HEALTH.lock().unwrap();
+    let health = health.entry(get_key(image_id, provider_id)).or_default();
+
+    health.consecutive_failures += 1;
+
+    if health.unhealthy_since.is_none()
+        && health.consecutive_failures >= circuit_breaker_config.failure_threshold
+    {
+        health.unhealthy_since = Some(Instant::now());
+
+        eprintln!(
+            "octoling: Image {} on provider {} marked unhealthy after {} consecutive failures, pausing it for {}s",
+            image_id, provider_id, health.consecutive_failures, circuit_breaker_config.cooldown
+        );
+    }
+}
+
+pub fn get_health_statuses() -> Vec<HealthStatus> {
+    GLOBAL_HEALTH
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((image_id, provider_id), health)| HealthStatus {
+            image_id: image_id.clone(),
+            provider_id: provider_id.clone(),
+            healthy: health.unhealthy_since.is_none(),
+            consecutive_failures: health.consecutive_failures,
+            unhealthy_for: health
+                .unhealthy_since
+                .map(|unhealthy_since| unhealthy_since.elapsed().as_secs()),
+        })
+        .collect()
+}
+
+/// Install a runner that isn't registered on GitHub to check if an image works again.
+async fn probe(image_config: &ImageConfig, provider_id: &str) -> bool {
+    let image_id = image_config.id.as_str();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    // The name is also the host name of the container, which is limited to 64 characters.
+    let runner_id = format!(
+        "octoling-probe-{}-{}",
+        timestamp,
+        GLOBAL_PROBE_COUNTER.fetch_add(1, Ordering::SeqCst)
+    );
+
+    info!(
+        "octoling: Probing image {} on provider {} with runner {}",
+        image_id, provider_id, runner_id
+    );
+
+    let result = manager::probe_runner(image_config, provider_id, runner_id.as_str()).await;
+
+    if let Err(error) = &result {
+        eprintln!(
+            "octoling: Probe of image {} on provider {} failed: {:?}",
+            image_id, provider_id, error
+        );
+    }
+
+    result.is_ok()
+}
+
+pub async fn probe_periodically() {
+    let circuit_breaker_config = &*GLOBAL_CIRCUIT_BREAKER_CONFIG;
+
+    if !circuit_breaker_config.enabled {
+        return;
+    }
+
+    let cooldown = Duration::from_secs(circuit_breaker_config.cooldown);
+
+    loop {
+        tokio::time::sleep(PROBE_CHECK_INTERVAL).await;
+
+        let keys: Vec<(String, String)> = GLOBAL_HEALTH
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, health)| {
+                health
+                    .unhealthy_since
+                    .is_some_and(|unhealthy_since| unhealthy_since.elapsed() >= cooldown)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for (image_id, provider_id) in keys {
+            let image_config = match config::get_image_config_by_id(image_id.as_str()) {
+                Some(image_config) => image_config,
+                None => continue,
+            };
+
+            // Probe runners count toward the limits like any other runner, try again on the next check.
+            if !scheduler::has_capacity_for_probe(&image_config, provider_id.as_str()) {
+                debug!(
+                    "octoling: Not probing image {} on provider {} while it is at capacity",
+                    image_id, provider_id
+                );
+                continue;
+            }
+
+            if probe(&image_config, provider_id.as_str()).await {
+                record_success(image_id.as_str(), provider_id.as_str());
+            } else if let Some(health) = GLOBAL_HEALTH
+                .lock()
+                .unwrap()
+                .get_mut(&get_key(image_id.as_str(), provider_id.as_str()))
+            {
+                // Wait for another cooldown before the next probe.
+                health.unhealthy_since = Some(Instant::now());
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::GLOBAL_WEBHOOK_CONFIG;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobStatus {
+    /// The job is waiting for a runner provisioned by this instance.
+    Queued,
+    /// The job cannot be handled by this instance.
+    Ignored,
+    /// The job was picked up by a runner provisioned by this instance.
+    InProgress,
+    Completed,
+}
+
+impl JobStatus {
+    pub fn is_pending(self) -> bool {
+        self == JobStatus::Queued || self == JobStatus::InProgress
+    }
+}
+
+/// A workflow job seen through a webhook delivery or the poller.
+#[derive(Clone, Debug)]
+pub struct JobInfo {
+    pub job_id: u64,
+    pub owner: String,
+    pub repository: String,
+    pub status: JobStatus,
+    /// The runner that actually picked up the job, which can differ from the one provisioned for it.
+    pub runner_name: Option<String>,
+    pub updated_at: Instant,
+}
+
+impl JobInfo {
+    fn new(job_id: u64, owner: &str, repository: &str) -> Self {
+        JobInfo {
+            job_id,
+            owner: String::from(owner),
+            repository: String::from(repository),
+            status: JobStatus::Queued,
+            runner_name: None,
+            updated_at: Instant::now(),
+        }
+    }
+}
+
+static GLOBAL_JOBS: Lazy<Mutex<HashMap<u64, JobInfo>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Forget jobs that weren't updated during the retention window, whatever their status.
+///
+/// A pending job whose completion was missed would be kept forever otherwise.
+fn prune_jobs(jobs: &mut HashMap<u64, JobInfo>) {
+    let retention = Duration::from_secs(GLOBAL_WEBHOOK_CONFIG.retention);
+
+    jobs.retain(|_, job_info| job_info.updated_at.elapsed() <= retention);
+}
+
+pub fn get_job_info(job_id: u64) -> Option<JobInfo> {
+    GLOBAL_JOBS.lock().unwrap().get(&job_id).cloned()
+}
+
+pub fn is_job_known(job_id: u64) -> bool {
+    GLOBAL_JOBS.lock().unwrap().contains_key(&job_id)
+}
+
+pub fn forget_job(job_id: u64) {
+    GLOBAL_JOBS.lock().unwrap().remove(&job_id);
+}
+
+/// Record a queued job, return false if the job was already known.
+pub fn mark_job_queued(job_id: u64, owner: &str, repository: &str) -> bool {
+    let mut jobs = GLOBAL_JOBS.lock().unwrap();
+
+    prune_jobs(&mut jobs);
+
+    if jobs.contains_key(&job_id) {
+        return false;
+    }
+
+    jobs.insert(job_id, JobInfo::new(job_id, owner, repository));
+
+    true
+}
+
+pub fn mark_job_ignored(job_id: u64) {
+    if let Some(job_info) = GLOBAL_JOBS.lock().unwrap().get_mut(&job_id) {
+        job_info.status = JobStatus::Ignored;
+        job_info.updated_at = Instant::now();
+    }
+}
+
+/// Record the runner that picked up a job, return false if it was already known or ignored.
+pub fn mark_job_in_progress(job_id: u64, owner: &str, repository: &str, runner_name: &str) -> bool {
+    let mut jobs = GLOBAL_JOBS.lock().unwrap();
+
+    prune_jobs(&mut jobs);
+
+    let job_info = jobs
+        .entry(job_id)
+        .or_insert_with(|| JobInfo::new(job_id, owner, repository));
+
+    if job_info.status == JobStatus::Completed
+        || job_info.status == JobStatus::Ignored
+        || job_info.runner_name.as_deref() == Some(runner_name)
+    {
+        return false;
+    }
+
+    job_info.status = JobStatus::InProgress;
+    job_info.runner_name = Some(String::from(runner_name));
+    job_info.updated_at = Instant::now();
+
+    true
+}
+
+/// Record the completion of a known job, return false if the job was already known as completed.
+///
+/// Jobs this instance never handled aren't recorded.
+pub fn mark_job_completed(job_id: u64) -> bool {
+    let mut jobs = GLOBAL_JOBS.lock().unwrap();
+
+    prune_jobs(&mut jobs);
+
+    let job_info = match jobs.get_mut(&job_id) {
+        Some(job_info) => job_info,
+        None => return true,
+    };
+
+    if job_info.status == JobStatus::Completed {
+        return false;
+    }
+
+    job_info.status = JobStatus::Completed;
+    job_info.updated_at = Instant::now();
+
+    true
+}
+
+/// Jobs of a repository that are still waiting for their completion.
+pub fn get_pending_jobs(owner: &str, repository: &str) -> Vec<JobInfo> {
+    GLOBAL_JOBS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|job_info| {
+            job_info.status.is_pending()
+                && job_info.owner == owner
+                && job_info.repository == repository
+        })
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,52 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Verbosity of the logs, errors are always printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Info,
+    Debug,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "error" => Ok(LogLevel::Error),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(format!(
+                "unknown log level \"{}\", expected error, info or debug",
+                value
+            )),
+        }
+    }
+}
+
+static GLOBAL_LOG_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+    GLOBAL_LOG_LEVEL.store(level as u8, Ordering::SeqCst);
+}
+
+pub fn is_enabled(level: LogLevel) -> bool {
+    level as u8 <= GLOBAL_LOG_LEVEL.load(Ordering::SeqCst)
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logging::is_enabled($crate::logging::LogLevel::Info) {
+            println!($($arg)*);
+        }
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::is_enabled($crate::logging::LogLevel::Debug) {
+            println!($($arg)*);
+        }
+    };
+}
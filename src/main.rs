@@ -1,23 +1,88 @@
 #![allow(dead_code)]
 
+#[macro_use]
+mod logging;
+
 mod api;
+mod cache;
+mod cli;
 mod config;
+mod delivery_log;
+mod health;
+mod jobs;
 mod manager;
+mod poller;
 mod provider;
+mod scheduler;
+mod server;
+mod shutdown;
+mod sweeper;
 mod utils;
 
-use api::api_routes;
-//use api::github_connector_routes;
-use api::github_webhook_routes;
+use cli::Command;
+
+use std::net::SocketAddr;
 
-use warp::Filter;
+async fn serve(listen: Option<SocketAddr>) -> bool {
+    tokio::spawn(manager::reconcile_periodically());
+    tokio::spawn(poller::poll_periodically());
+    tokio::spawn(sweeper::sweep_periodically());
+    tokio::spawn(scheduler::dispatch_periodically());
+    tokio::spawn(health::probe_periodically());
+    tokio::spawn(manager::enforce_runner_timeouts_periodically());
+
+    server::run(listen).await
+}
 
 #[tokio::main]
 async fn main() {
-    config::load();
+    let arguments = match cli::parse(std::env::args().skip(1)) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            eprintln!("octoling: {}\n\n{}", error, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(log_level) = arguments.log_level {
+        logging::set_level(log_level);
+    }
+
+    if let Some(config_path) = &arguments.config_path {
+        config::set_path(config_path);
+    }
+
+    match arguments.command {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Command::ValidateConfig => {
+            if !cli::validate_config() {
+                std::process::exit(1);
+            }
+
+            return;
+        }
+        _ => {}
+    }
+
+    if !cli::load_config() {
+        std::process::exit(1);
+    }
+
     provider::init();
 
-    let routes = api_routes().or(github_webhook_routes());
+    let success = match &arguments.command {
+        Command::Serve => serve(arguments.listen).await,
+        Command::ListRunners => cli::list_runners().await,
+        Command::Destroy { runner_id } => cli::destroy(runner_id).await,
+        Command::Gc => cli::gc().await,
+        Command::Provision { image_id } => cli::provision(image_id).await,
+        Command::Help | Command::ValidateConfig => true,
+    };
 
-    warp::serve(routes).run(([127, 0, 0, 1], 8000)).await;
+    if !success {
+        std::process::exit(1);
+    }
 }
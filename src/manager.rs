@@ -1,16 +1,28 @@
-use crate::config::{GithubConfig, ImageConfig};
+use crate::cache::{self, CacheError, ProvisioningArtifacts};
+use crate::config::{
+    self, GithubConfig, GithubJitConfig, ImageConfig, GLOBAL_GITHUB_CONFIG, GLOBAL_MANAGER_CONFIG,
+    GLOBAL_RETRY_CONFIG,
+};
 use crate::provider::GLOBAL_PROVIDER;
-use crate::provider::{self, ProviderError, RunOptions, Runner};
+use crate::provider::{self, Provider, ProviderError, RunOptions, Runner};
+use crate::scheduler;
 
-use std::sync::Mutex;
-use std::time::Duration;
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ManagerError {
     ProviderNotFound,
     Provider(ProviderError),
     TokenRequestFailed,
+    JitConfigRequestFailed,
     InstallationFailed,
+    Cancelled,
+    Cache(CacheError),
 }
 
 impl From<ProviderError> for ManagerError {
@@ -19,10 +31,145 @@ impl From<ProviderError> for ManagerError {
     }
 }
 
+impl From<CacheError> for ManagerError {
+    fn from(err: CacheError) -> ManagerError {
+        ManagerError::Cache(err)
+    }
+}
+
 pub type Result<T> = std::result::Result<T, ManagerError>;
 
 // TODO: https://docs.github.com/en/rest/reference/actions#list-runner-applications-for-a-repository
 const RUNNER_DL_URL: &str = "https://github.com/actions/runner/releases/download/v2.283.3/actions-runner-linux-x64-2.283.3.tar.gz";
+const DOCKER_INSTALL_SCRIPT_URL: &str = "https://get.docker.com/";
+
+const RUNNER_NAME_PREFIX: &str = "octoling-";
+
+// Labels that config.sh adds by itself but that must be explicit with a JIT configuration, along with the
+// architecture.
+const JIT_DEFAULT_LABELS: [&str; 2] = ["self-hosted", "Linux"];
+
+/// How a runner registers itself to GitHub.
+pub enum RunnerRegistration {
+    /// A registration token given to config.sh.
+    Token(String),
+    /// A JIT configuration given to run.sh, the runner was already created on GitHub side.
+    JitConfig(GithubJitConfig),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunnerState {
+    Provisioning,
+    /// The runner is registered and waiting for a job.
+    Idle,
+    Busy,
+    Stopping,
+}
+
+/// A runner created by this instance.
+#[derive(Clone, Debug)]
+pub struct RunnerInfo {
+    pub runner_id: String,
+    pub provider_id: String,
+    pub image_id: String,
+    pub owner: String,
+    pub repository: String,
+    /// The job that triggered the creation of the runner.
+    pub job_id: u64,
+    /// The job the runner actually picked up, GitHub can give it any job matching its labels.
+    pub assigned_job_id: Option<u64>,
+    pub state: RunnerState,
+    /// Set to abort the provisioning of the runner.
+    pub cancelled: Arc<AtomicBool>,
+    pub created_at: Instant,
+    /// When the runner was ready to pick up a job.
+    pub idle_since: Option<Instant>,
+}
+
+static GLOBAL_RUNNERS: Lazy<Mutex<HashMap<String, RunnerInfo>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// GitHub runners found stale by the reconciliation, with when they were first found stale.
+static GLOBAL_STALE_RUNNERS: Lazy<Mutex<HashMap<u64, Instant>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Label given to every runner created by this instance.
+fn get_instance_label() -> String {
+    format!(
+        "octoling-instance-{}",
+        GLOBAL_MANAGER_CONFIG.get_instance_id()
+    )
+}
+
+fn track_runner(runner_info: RunnerInfo) {
+    GLOBAL_RUNNERS
+        .lock()
+        .unwrap()
+        .insert(runner_info.runner_id.clone(), runner_info);
+}
+
+fn forget_runner(runner_id: &str) {
+    GLOBAL_RUNNERS.lock().unwrap().remove(runner_id);
+
+    // A slot was freed for the jobs waiting for capacity.
+    scheduler::dispatch();
+}
+
+pub fn get_runner_info(runner_id: &str) -> Option<RunnerInfo> {
+    GLOBAL_RUNNERS.lock().unwrap().get(runner_id).cloned()
+}
+
+pub fn get_runner_infos() -> Vec<RunnerInfo> {
+    GLOBAL_RUNNERS.lock().unwrap().values().cloned().collect()
+}
+
+/// Whether a runner was provisioned for a job or picked it up.
+pub fn has_runner_for_job(job_id: u64) -> bool {
+    GLOBAL_RUNNERS.lock().unwrap().values().any(|runner_info| {
+        runner_info.job_id == job_id || runner_info.assigned_job_id == Some(job_id)
+    })
+}
+
+/// Record the job a runner picked up, return false if the runner isn't tracked by this instance.
+pub fn assign_job_to_runner(runner_id: &str, job_id: u64) -> bool {
+    match GLOBAL_RUNNERS.lock().unwrap().get_mut(runner_id) {
+        Some(runner_info) => {
+            runner_info.assigned_job_id = Some(job_id);
+            runner_info.state = RunnerState::Busy;
+
+            true
+        }
+        None => false,
+    }
+}
+
+/// Mark a provisioned runner as waiting for a job, return false if its provisioning was cancelled meanwhile.
+fn mark_runner_idle(runner_id: &str) -> bool {
+    match GLOBAL_RUNNERS.lock().unwrap().get_mut(runner_id) {
+        Some(runner_info) => {
+            if runner_info.cancelled.load(Ordering::SeqCst) {
+                return false;
+            }
+
+            // The runner may already have picked up a job.
+            if runner_info.state == RunnerState::Provisioning {
+                runner_info.state = RunnerState::Idle;
+                runner_info.idle_since = Some(Instant::now());
+            }
+
+            true
+        }
+        None => false,
+    }
+}
+
+fn ensure_not_cancelled(cancelled: &AtomicBool) -> Result<()> {
+    if cancelled.load(Ordering::SeqCst) {
+        Err(ManagerError::Cancelled)
+    } else {
+        Ok(())
+    }
+}
 
 fn ensure_success_error_code(error_code: i32) -> Result<()> {
     if error_code != 0 {
@@ -32,34 +179,110 @@ fn ensure_success_error_code(error_code: i32) -> Result<()> {
     }
 }
 
-fn setup_runner(
-    runner: &Mutex<Box<dyn Runner>>,
-    label: &str,
-    registration_token: &str,
-    repository_url: &str,
-    runner_id: &str,
+/// Run a setup step that can safely be run again until it succeeds or runs out of attempts.
+fn retry_step<F>(runner_id: &str, step: &str, cancelled: &AtomicBool, mut run: F) -> Result<()>
+where
+    F: FnMut() -> Result<()>,
+{
+    let retry_config = &*GLOBAL_RETRY_CONFIG;
+    let mut attempt = 1;
+
+    loop {
+        let result = run();
+
+        if result.is_ok() || attempt >= retry_config.step_attempts {
+            return result;
+        }
+
+        eprintln!(
+            "octoling: {} failed on runner {} (attempt {}/{}): {:?}, retrying",
+            step,
+            runner_id,
+            attempt,
+            retry_config.step_attempts,
+            result.unwrap_err()
+        );
+
+        std::thread::sleep(Duration::from_secs(retry_config.step_delay));
+        ensure_not_cancelled(cancelled)?;
+
+        attempt += 1;
+    }
+}
+
+/// Push a host cached file into the runner, or download it from the runner when there is none.
+fn provide_file(
+    runner: &dyn Runner,
+    cached_path: Option<&Path>,
+    url: &str,
+    destination: &str,
+    options: &RunOptions,
 ) -> Result<()> {
-    let mut options = RunOptions::default();
+    if let Some(cached_path) = cached_path {
+        match runner.push_file(cached_path, destination) {
+            Ok(()) => return Ok(()),
+            // Some storage backends cannot be reached from the host, download from the runner instead.
+            Err(error) => eprintln!(
+                "octoling: Cannot push {} into the runner: {:?}, downloading it instead",
+                cached_path.display(),
+                error
+            ),
+        }
+    }
 
-    let runner = runner.lock().unwrap();
+    ensure_success_error_code(runner.run(&["curl", "-L", url, "-o", destination], options)?)
+}
 
-    ensure_success_error_code(runner.run(&["apt-get", "update"], &options)?)?;
-    ensure_success_error_code(runner.run(
-        &["apt-get", "install", "-y", "curl", "tar", "gzip", "sudo"],
-        &options,
-    )?)?;
-    ensure_success_error_code(runner.run(
-        &["curl", "https://get.docker.com/", "-o", "install_docker.sh"],
-        &options,
-    )?)?;
-    ensure_success_error_code(
-        runner.run(&["sh", "install_docker.sh", "install", "runner"], &options)?,
+/// Install the runner and its dependencies, blocking until every step is done.
+fn install_runner(
+    runner: &dyn Runner,
+    artifacts: &ProvisioningArtifacts,
+    runner_id: &str,
+    cancelled: &AtomicBool,
+) -> Result<()> {
+    let options = RunOptions::default();
+
+    ensure_not_cancelled(cancelled)?;
+    retry_step(runner_id, "apt-get update", cancelled, || {
+        ensure_success_error_code(runner.run(&["apt-get", "update"], &options)?)
+    })?;
+    retry_step(runner_id, "apt-get install", cancelled, || {
+        ensure_success_error_code(runner.run(
+            &["apt-get", "install", "-y", "curl", "tar", "gzip", "sudo"],
+            &options,
+        )?)
+    })?;
+    ensure_not_cancelled(cancelled)?;
+    retry_step(
+        runner_id,
+        "Docker install script download",
+        cancelled,
+        || {
+            provide_file(
+                runner,
+                artifacts.docker_install_script.as_deref(),
+                DOCKER_INSTALL_SCRIPT_URL,
+                "/install_docker.sh",
+                &options,
+            )
+        },
     )?;
+    retry_step(runner_id, "Docker install", cancelled, || {
+        ensure_success_error_code(
+            runner.run(&["sh", "install_docker.sh", "install", "runner"], &options)?,
+        )
+    })?;
 
-    ensure_success_error_code(runner.run(
-        &["curl", "-L", RUNNER_DL_URL, "-o", "runner.tar.gz"],
-        &options,
-    )?)?;
+    ensure_not_cancelled(cancelled)?;
+    retry_step(runner_id, "Runner download", cancelled, || {
+        provide_file(
+            runner,
+            artifacts.runner_archive.as_deref(),
+            RUNNER_DL_URL,
+            "/runner.tar.gz",
+            &options,
+        )
+    })?;
     ensure_success_error_code(runner.run(&["useradd", "-m", "runner"], &options)?)?;
     ensure_success_error_code(runner.run(
         &[
@@ -87,125 +310,670 @@ fn setup_runner(
             "/runner",
         ],
         &options,
-    )?)?;
+    )?)
+}
 
-    options.cwd = String::from("/runner");
+/// Register an installed runner on GitHub and start it, blocking until every step is done.
+fn register_runner(
+    runner: &dyn Runner,
+    labels: &[String],
+    registration: &RunnerRegistration,
+    repository_url: &str,
+    runner_id: &str,
+    cancelled: &AtomicBool,
+) -> Result<()> {
+    let options = RunOptions {
+        cwd: String::from("/runner"),
+        ..RunOptions::default()
+    };
 
-    let mut labels = String::from("octoling");
-    labels.push(',');
-    labels.push_str(label);
+    ensure_not_cancelled(cancelled)?;
 
-    // https://docs.github.com/en/rest/reference/actions#create-a-registration-token-for-a-repository
-    // https://github.com/github/platform-samples/blob/master/api/bash/migrate-repos-in-org.sh#L126
-    // reqwest
-    ensure_success_error_code(runner.run(
-        &[
-            "sudo",
-            "-u",
-            "runner",
-            "bash",
-            "config.sh",
-            "--unattended",
-            "--ephemeral",
-            "--url",
-            repository_url,
-            "--token",
-            registration_token,
-            "--name",
-            // Do not trust OS naming
-            runner_id,
-            "--labels",
-            labels.as_str(),
-        ],
-        &options,
-    )?)?;
+    match registration {
+        RunnerRegistration::Token(registration_token) => {
+            let labels = format!("octoling,{},{}", get_instance_label(), labels.join(","));
+
+            // https://docs.github.com/en/rest/reference/actions#create-a-registration-token-for-a-repository
+            // https://github.com/github/platform-samples/blob/master/api/bash/migrate-repos-in-org.sh#L126
+            // reqwest
+            ensure_success_error_code(runner.run(
+                &[
+                    "sudo",
+                    "-u",
+                    "runner",
+                    "bash",
+                    "config.sh",
+                    "--unattended",
+                    "--ephemeral",
+                    "--url",
+                    repository_url,
+                    "--token",
+                    registration_token.as_str(),
+                    "--name",
+                    // Do not trust OS naming
+                    runner_id,
+                    "--labels",
+                    labels.as_str(),
+                ],
+                &options,
+            )?)?;
+
+            ensure_success_error_code(
+                runner.run(&["bash", "svc.sh", "install", "runner"], &options)?,
+            )?;
+            ensure_success_error_code(runner.run(&["bash", "svc.sh", "start"], &options)?)?;
+        }
+        RunnerRegistration::JitConfig(jit_config) => {
+            // The runner is already registered on GitHub side, we only need to run it in the background.
+            ensure_success_error_code(runner.run(
+                &[
+                    "bash",
+                    "-c",
+                    "nohup sudo -u runner bash run.sh --jitconfig \"$1\" > run.log 2>&1 < /dev/null &",
+                    "octoling",
+                    jit_config.encoded_jit_config.as_str(),
+                ],
+                &options,
+            )?)?;
+        }
+    }
 
-    ensure_success_error_code(runner.run(&["bash", "svc.sh", "install", "runner"], &options)?)?;
-    ensure_success_error_code(runner.run(&["bash", "svc.sh", "start"], &options)?)?;
     Ok(())
 }
 
+/// Run a blocking operation on a provider, its lock can be held for minutes while a container is created.
+async fn with_provider<T, F>(provider: &'static Mutex<Box<dyn Provider>>, operation: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn Provider) -> T + Send + 'static,
+{
+    let result =
+        tokio::task::spawn_blocking(move || operation(&mut **provider.lock().unwrap())).await;
+
+    match result {
+        Ok(result) => result,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    }
+}
+
 pub async fn start_new_clean_runner(
     image_config: &ImageConfig,
+    provider_id: &str,
     runner_id: &str,
 ) -> Result<Box<dyn Runner>> {
-    if let Some(provider) = provider::get_provider(image_config.provider_id.as_str()) {
-        let mut provider = provider.lock().unwrap();
+    let provider = provider::get_provider(provider_id).ok_or(ManagerError::ProviderNotFound)?;
+    let image_config = image_config.clone();
+    let runner_id = String::from(runner_id);
 
-        let runner = provider.create(image_config, runner_id)?;
+    // Creating a container can download a whole image.
+    with_provider(provider, move |provider| {
+        let runner = provider.create(&image_config, runner_id.as_str())?;
 
         if let Err(startup_error) = runner.start() {
             // Ensure that we destroy on startup error.
-            let _ = provider.destroy(runner_id);
+            let _ = provider.destroy(runner_id.as_str());
 
             // Return original startup error
             return Err(ManagerError::from(startup_error));
         }
 
-        return Ok(runner);
-    }
+        Ok(runner)
+    })
+    .await
+}
+
+pub async fn destroy_runner_container(provider_id: &str, runner_id: &str) -> Result<()> {
+    let provider = provider::get_provider(provider_id).ok_or(ManagerError::ProviderNotFound)?;
+    let runner_id = String::from(runner_id);
 
-    Err(ManagerError::ProviderNotFound)
+    with_provider(provider, move |provider| {
+        provider.destroy(runner_id.as_str())
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Remove a runner from GitHub so that no offline entry is left behind.
+async fn deregister_runner(runner_id: &str) {
+    // Runner names are prefixed by their repository, use that when the runner isn't tracked anymore.
+    let github_configs: Vec<GithubConfig> = match get_runner_info(runner_id) {
+        Some(runner_info) => config::get_github_config_by_owner_and_repo(
+            runner_info.owner.as_str(),
+            runner_info.repository.as_str(),
+        )
+        .into_iter()
+        .collect(),
+        None => GLOBAL_GITHUB_CONFIG
+            .iter()
+            .filter(|github_config| runner_id.starts_with(&github_config.get_runner_name_prefix()))
+            .cloned()
+            .collect(),
+    };
+
+    for github_config in github_configs {
+        if let Some(github_runner) = github_config.find_repo_runner_by_name(runner_id).await {
+            if github_config.delete_repo_runner(github_runner.id).await {
+                info!(
+                    "octoling: Runner {} was deregistered from GitHub",
+                    runner_id
+                );
+            } else {
+                eprintln!(
+                    "octoling: Cannot deregister runner {} from GitHub",
+                    runner_id
+                );
+            }
+        }
+    }
 }
 
 pub async fn destroy_runner_with_runner_id(runner_id: &str) -> Result<()> {
+    if let Some(runner_info) = get_runner_info(runner_id) {
+        return destroy_runner(runner_info.provider_id.as_str(), runner_id).await;
+    }
+
+    let mut result = Err(ManagerError::Provider(ProviderError::RunnerNotFound));
+
     for provider_id in GLOBAL_PROVIDER.keys() {
-        let result = destroy_runner(provider_id.as_str(), runner_id).await;
+        result = destroy_runner_container(provider_id.as_str(), runner_id).await;
+
+        if result != Err(ManagerError::Provider(ProviderError::RunnerNotFound)) {
+            break;
+        }
+    }
+
+    deregister_runner(runner_id).await;
+
+    result
+}
+
+pub async fn destroy_runner(provider_id: &str, runner_id: &str) -> Result<()> {
+    let result = destroy_runner_container(provider_id, runner_id).await;
+
+    deregister_runner(runner_id).await;
+    forget_runner(runner_id);
+
+    result
+}
+
+/// Abort the runner provisioned for a job that was cancelled before any runner picked it up.
+///
+/// When another job took the runner of the cancelled job, the runner provisioned for that other job, or an idle
+/// runner of the same image and repository, is now surplus and aborted instead.
+/// A runner still being provisioned is torn down by its provisioning task, an idle one is destroyed here.
+/// Return the affected runner if any.
+pub async fn cancel_runner_for_job(job_id: u64) -> Result<Option<String>> {
+    let runner_id = {
+        let mut runners = GLOBAL_RUNNERS.lock().unwrap();
+
+        let own_runner_info = match runners
+            .values()
+            .find(|runner_info| runner_info.job_id == job_id)
+        {
+            Some(runner_info) => runner_info.clone(),
+            None => return Ok(None),
+        };
 
-        match result {
-            Ok(_) => return Ok(()),
-            Err(error) => {
-                if error != ManagerError::Provider(ProviderError::RunnerNotFound) {
-                    return Err(error);
+        let runner_info = match own_runner_info.assigned_job_id {
+            None => runners.get_mut(&own_runner_info.runner_id),
+            // The job itself ran on its runner, it wasn't cancelled before being picked up.
+            Some(assigned_job_id) if assigned_job_id == job_id => return Ok(None),
+            Some(assigned_job_id) => {
+                let is_surplus = |runner_info: &RunnerInfo| {
+                    runner_info.assigned_job_id.is_none()
+                        && runner_info.image_id == own_runner_info.image_id
+                        && runner_info.owner == own_runner_info.owner
+                        && runner_info.repository == own_runner_info.repository
+                };
+                let surplus_runner_id = runners
+                    .values()
+                    .find(|runner_info| {
+                        runner_info.job_id == assigned_job_id && is_surplus(runner_info)
+                    })
+                    .or_else(|| {
+                        runners.values().find(|runner_info| {
+                            runner_info.state == RunnerState::Idle && is_surplus(runner_info)
+                        })
+                    })
+                    .map(|runner_info| runner_info.runner_id.clone());
+
+                match surplus_runner_id {
+                    Some(surplus_runner_id) => runners.get_mut(&surplus_runner_id),
+                    None => return Ok(None),
                 }
             }
+        };
+
+        match runner_info {
+            Some(runner_info) if runner_info.state == RunnerState::Provisioning => {
+                runner_info.cancelled.store(true, Ordering::SeqCst);
+
+                return Ok(Some(runner_info.runner_id.clone()));
+            }
+            Some(runner_info) if runner_info.state == RunnerState::Idle => {
+                runner_info.state = RunnerState::Stopping;
+
+                runner_info.runner_id.clone()
+            }
+            _ => return Ok(None),
+        }
+    };
+
+    destroy_runner_with_runner_id(runner_id.as_str()).await?;
+
+    Ok(Some(runner_id))
+}
+
+/// Whether a container of the runner exists on any provider, it may belong to another process like a running
+/// server when called from the command line.
+async fn has_runner_container(runner_id: &str) -> bool {
+    for provider in GLOBAL_PROVIDER.values() {
+        let runner_id = String::from(runner_id);
+
+        let exists = with_provider(provider, move |provider| {
+            provider.get(runner_id.as_str()).is_ok()
+        })
+        .await;
+
+        if exists {
+            return true;
         }
     }
 
-    Err(ManagerError::Provider(ProviderError::RunnerNotFound))
+    false
 }
 
-pub async fn destroy_runner(provider_id: &str, runner_id: &str) -> Result<()> {
-    if let Some(provider) = provider::get_provider(provider_id) {
-        let mut provider = provider.lock().unwrap();
+/// Delete offline runners left on GitHub by previous runs of this instance or failed provisioning.
+///
+/// Only runners labeled by this instance that have no container and stayed offline for `grace_period` are
+/// deleted, other instances may share the repositories and JIT runners are offline until they start.
+pub async fn reconcile(grace_period: Duration) {
+    let instance_label = get_instance_label();
+    let mut stale_runner_ids = HashSet::new();
 
-        provider.destroy(runner_id)?;
+    for github_config in GLOBAL_GITHUB_CONFIG.iter() {
+        if !github_config.enabled {
+            continue;
+        }
 
-        Ok(())
-    } else {
-        Err(ManagerError::ProviderNotFound)
+        let github_runners = match github_config.list_repo_runners().await {
+            Some(github_runners) => github_runners,
+            None => {
+                eprintln!(
+                    "octoling: Cannot list runners of {}/{}",
+                    github_config.owner, github_config.repository
+                );
+                continue;
+            }
+        };
+
+        for github_runner in github_runners {
+            let is_stale = github_runner.name.starts_with(RUNNER_NAME_PREFIX)
+                && github_runner.status == "offline"
+                && github_runner.has_label(instance_label.as_str())
+                && get_runner_info(github_runner.name.as_str()).is_none()
+                && !has_runner_container(github_runner.name.as_str()).await;
+
+            if !is_stale {
+                continue;
+            }
+
+            stale_runner_ids.insert(github_runner.id);
+
+            let stale_since = *GLOBAL_STALE_RUNNERS
+                .lock()
+                .unwrap()
+                .entry(github_runner.id)
+                .or_insert_with(Instant::now);
+
+            if stale_since.elapsed() < grace_period {
+                continue;
+            }
+
+            if github_config.delete_repo_runner(github_runner.id).await {
+                info!(
+                    "octoling: Stale runner {} was deregistered from GitHub",
+                    github_runner.name
+                );
+            } else {
+                eprintln!(
+                    "octoling: Cannot deregister stale runner {} from GitHub",
+                    github_runner.name
+                );
+            }
+        }
     }
+
+    // Runners that came online or were deleted start over.
+    GLOBAL_STALE_RUNNERS
+        .lock()
+        .unwrap()
+        .retain(|runner_id, _| stale_runner_ids.contains(runner_id));
 }
 
-pub async fn start_new_runner(
+pub async fn reconcile_periodically() {
+    let interval = Duration::from_secs(GLOBAL_MANAGER_CONFIG.reconcile_interval);
+    let grace_period = Duration::from_secs(GLOBAL_MANAGER_CONFIG.reconcile_grace_period);
+
+    loop {
+        reconcile(grace_period).await;
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Abort the provisioning of every runner, return the number of affected runners.
+pub fn cancel_provisioning_runners() -> usize {
+    let runners = GLOBAL_RUNNERS.lock().unwrap();
+    let mut count = 0;
+
+    for runner_info in runners.values() {
+        if runner_info.state == RunnerState::Provisioning {
+            runner_info.cancelled.store(true, Ordering::SeqCst);
+            count += 1;
+        }
+    }
+
+    count
+}
+
+/// Destroy every runner that isn't being provisioned.
+pub async fn destroy_all_runners() {
+    let runner_infos: Vec<RunnerInfo> = {
+        let mut runners = GLOBAL_RUNNERS.lock().unwrap();
+
+        runners
+            .values_mut()
+            .filter(|runner_info| {
+                runner_info.state == RunnerState::Idle || runner_info.state == RunnerState::Busy
+            })
+            .map(|runner_info| {
+                runner_info.state = RunnerState::Stopping;
+
+                runner_info.clone()
+            })
+            .collect()
+    };
+
+    for runner_info in runner_infos {
+        match destroy_runner(
+            runner_info.provider_id.as_str(),
+            runner_info.runner_id.as_str(),
+        )
+        .await
+        {
+            Ok(()) => info!("octoling: Runner {} was destroyed", runner_info.runner_id),
+            Err(error) => eprintln!(
+                "octoling: Cannot destroy runner {}: {:?}",
+                runner_info.runner_id, error
+            ),
+        }
+    }
+}
+
+/// Stop the runners that exceeded the timeouts of their image, return them along with the reason.
+fn take_timed_out_runners() -> Vec<(RunnerInfo, &'static str)> {
+    let mut runners = GLOBAL_RUNNERS.lock().unwrap();
+    let mut timed_out_runners = Vec::new();
+
+    for runner_info in runners.values_mut() {
+        if runner_info.state == RunnerState::Stopping {
+            continue;
+        }
+
+        let image_config = match config::get_image_config_by_id(runner_info.image_id.as_str()) {
+            Some(image_config) => image_config,
+            None => continue,
+        };
+
+        let is_expired =
+            |max_duration: Option<u64>, since: Option<Instant>| match (max_duration, since) {
+                (Some(max_duration), Some(since)) => {
+                    since.elapsed() >= Duration::from_secs(max_duration)
+                }
+                _ => false,
+            };
+
+        let reason = if is_expired(image_config.max_lifetime, Some(runner_info.created_at)) {
+            "maximum lifetime"
+        } else if runner_info.state == RunnerState::Idle
+            && is_expired(image_config.max_idle, runner_info.idle_since)
+        {
+            "maximum idle time"
+        } else {
+            continue;
+        };
+
+        // A runner still being provisioned might be stuck, its provisioning is aborted and its container destroyed.
+        runner_info.cancelled.store(true, Ordering::SeqCst);
+        runner_info.state = RunnerState::Stopping;
+
+        timed_out_runners.push((runner_info.clone(), reason));
+    }
+
+    timed_out_runners
+}
+
+/// Destroy the runners that exceeded the timeouts of their image.
+pub async fn enforce_runner_timeouts() {
+    for (runner_info, reason) in take_timed_out_runners() {
+        info!(
+            "octoling: Runner {} reached its {}, destroying it",
+            runner_info.runner_id, reason
+        );
+
+        if let Err(error) = destroy_runner(
+            runner_info.provider_id.as_str(),
+            runner_info.runner_id.as_str(),
+        )
+        .await
+        {
+            eprintln!(
+                "octoling: Cannot destroy runner {}: {:?}",
+                runner_info.runner_id, error
+            );
+        }
+    }
+}
+
+pub async fn enforce_runner_timeouts_periodically() {
+    let interval = Duration::from_secs(GLOBAL_MANAGER_CONFIG.watchdog_interval);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        enforce_runner_timeouts().await;
+    }
+}
+
+/// Labels of a runner registered with a JIT configuration, without duplicates as labels are case insensitive.
+fn get_jit_labels<'a>(image_config: &'a ImageConfig, instance_label: &'a str) -> Vec<&'a str> {
+    let mut labels: Vec<&str> = Vec::new();
+    let candidate_labels = JIT_DEFAULT_LABELS
+        .iter()
+        .copied()
+        .chain(image_config.get_architecture_label())
+        .chain(["octoling", instance_label])
+        .chain(image_config.labels.iter().map(String::as_str));
+
+    for label in candidate_labels {
+        if !labels.iter().any(|other| other.eq_ignore_ascii_case(label)) {
+            labels.push(label);
+        }
+    }
+
+    labels
+}
+
+async fn provision_runner(
     image_config: &ImageConfig,
+    provider_id: &str,
     github_config: GithubConfig,
-    label: &str,
     runner_id: &str,
+    cancelled: Arc<AtomicBool>,
 ) -> Result<Mutex<Box<dyn Runner>>> {
-    let runner_token = github_config
-        .request_new_repo_runner_token()
-        .await
-        .ok_or(ManagerError::TokenRequestFailed)?;
-    let repository_url = github_config.get_repo_url();
-    let runner = Mutex::new(start_new_clean_runner(image_config, runner_id).await?);
+    let artifacts =
+        cache::fetch_provisioning_artifacts(RUNNER_DL_URL, DOCKER_INSTALL_SCRIPT_URL).await?;
+
+    ensure_not_cancelled(&cancelled)?;
+
+    // The runner is only registered on GitHub once its container exists, a failed creation leaves nothing behind.
+    let runner = start_new_clean_runner(image_config, provider_id, runner_id).await?;
 
     // FIXME: find a better way to know when the network is ready.
     // TODO: Also move to Runner::start?
-    std::thread::sleep(Duration::from_secs(5));
+    tokio::time::sleep(Duration::from_secs(5)).await;
 
-    if let Err(error) = setup_runner(
-        &runner,
-        label,
-        runner_token.as_str(),
-        repository_url.as_str(),
-        runner_id,
-    ) {
-        let _ = runner.lock().unwrap().stop();
-        let _ = destroy_runner(image_config.provider_id.as_str(), runner_id).await;
+    let registration = if github_config.use_jit_config {
+        let instance_label = get_instance_label();
+        let labels = get_jit_labels(image_config, instance_label.as_str());
+
+        github_config
+            .request_new_repo_jit_config(runner_id, &labels)
+            .await
+            .map(RunnerRegistration::JitConfig)
+            .ok_or(ManagerError::JitConfigRequestFailed)
+    } else {
+        github_config
+            .request_new_repo_runner_token()
+            .await
+            .map(RunnerRegistration::Token)
+            .ok_or(ManagerError::TokenRequestFailed)
+    };
+    let registration = match registration {
+        Ok(registration) => registration,
+        Err(error) => {
+            let _ = runner.stop();
+
+            return Err(error);
+        }
+    };
+    let repository_url = github_config.get_repo_url();
+    let labels = image_config.labels.clone();
+    let setup_runner_id = String::from(runner_id);
+
+    // The setup steps block until the commands exit, keep them off the async workers.
+    let setup = tokio::task::spawn_blocking(move || {
+        let result = install_runner(&*runner, &artifacts, setup_runner_id.as_str(), &cancelled)
+            .and_then(|_| {
+                register_runner(
+                    &*runner,
+                    &labels,
+                    &registration,
+                    repository_url.as_str(),
+                    setup_runner_id.as_str(),
+                    &cancelled,
+                )
+            });
+
+        (runner, result)
+    })
+    .await;
+
+    let (runner, result) = match setup {
+        Ok(setup) => setup,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    };
+
+    if let Err(error) = result {
+        let _ = runner.stop();
 
         return Err(error);
     }
 
-    Ok(runner)
+    Ok(Mutex::new(runner))
+}
+
+/// Create a runner and run the installation steps in it without registering it on GitHub, then destroy it.
+pub async fn probe_runner(
+    image_config: &ImageConfig,
+    provider_id: &str,
+    runner_id: &str,
+) -> Result<()> {
+    let artifacts =
+        cache::fetch_provisioning_artifacts(RUNNER_DL_URL, DOCKER_INSTALL_SCRIPT_URL).await?;
+    let runner = start_new_clean_runner(image_config, provider_id, runner_id).await?;
+
+    // FIXME: find a better way to know when the network is ready.
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    let install_runner_id = String::from(runner_id);
+    let install = tokio::task::spawn_blocking(move || {
+        install_runner(
+            &*runner,
+            &artifacts,
+            install_runner_id.as_str(),
+            &AtomicBool::new(false),
+        )
+    })
+    .await;
+
+    let result = match install {
+        Ok(result) => result,
+        Err(error) => std::panic::resume_unwind(error.into_panic()),
+    };
+    let destroy_result = destroy_runner_container(provider_id, runner_id).await;
+
+    result.and(destroy_result)
+}
+
+/// Track a runner before its provisioning starts so that it counts toward capacity limits.
+pub fn reserve_runner(
+    image_config: &ImageConfig,
+    provider_id: &str,
+    github_config: &GithubConfig,
+    runner_id: &str,
+    job_id: u64,
+) {
+    track_runner(RunnerInfo {
+        runner_id: String::from(runner_id),
+        provider_id: String::from(provider_id),
+        image_id: image_config.id.clone(),
+        owner: github_config.owner.clone(),
+        repository: github_config.repository.clone(),
+        job_id,
+        assigned_job_id: None,
+        state: RunnerState::Provisioning,
+        cancelled: Arc::new(AtomicBool::new(false)),
+        created_at: Instant::now(),
+        idle_since: None,
+    });
+}
+
+pub async fn start_new_runner(
+    image_config: &ImageConfig,
+    provider_id: &str,
+    github_config: GithubConfig,
+    runner_id: &str,
+    job_id: u64,
+) -> Result<Mutex<Box<dyn Runner>>> {
+    let cancelled = match get_runner_info(runner_id) {
+        Some(runner_info) => runner_info.cancelled,
+        None => {
+            reserve_runner(image_config, provider_id, &github_config, runner_id, job_id);
+
+            get_runner_info(runner_id).unwrap().cancelled
+        }
+    };
+
+    let mut result = provision_runner(
+        image_config,
+        provider_id,
+        github_config,
+        runner_id,
+        cancelled,
+    )
+    .await;
+
+    if result.is_ok() && !mark_runner_idle(runner_id) {
+        result = Err(ManagerError::Cancelled);
+    }
+
+    if result.is_err() {
+        // The runner might be registered on GitHub even if its setup failed.
+        let _ = destroy_runner(provider_id, runner_id).await;
+    }
+
+    result
 }
@@ -0,0 +1,173 @@
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::api::{dispatch_workflow_job_event, Repository, User, WorkflowJob, WorkflowJobEvent};
+use crate::config::{GithubConfig, GLOBAL_GITHUB_CONFIG, GLOBAL_POLLER_CONFIG};
+use crate::jobs;
+
+const PAGE_SIZE: usize = 100;
+
+#[derive(Clone, Debug, Deserialize)]
+struct WorkflowRun {
+    pub id: u64,
+    pub actor: User,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct WorkflowRunList {
+    pub workflow_runs: Vec<WorkflowRun>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct WorkflowJobList {
+    pub jobs: Vec<WorkflowJob>,
+}
+
+// https://docs.github.com/en/rest/actions/workflow-runs#list-workflow-runs-for-a-repository
+async fn list_workflow_runs(
+    github_config: &GithubConfig,
+    status: &str,
+) -> Option<Vec<WorkflowRun>> {
+    let mut workflow_runs = Vec::new();
+
+    for page in 1.. {
+        let workflow_run_list: WorkflowRunList = github_config
+            .get_repo_json(
+                format!(
+                    "actions/runs?status={}&per_page={}&page={}",
+                    status, PAGE_SIZE, page
+                )
+                .as_str(),
+            )
+            .await?;
+        let is_last_page = workflow_run_list.workflow_runs.len() < PAGE_SIZE;
+
+        workflow_runs.extend(workflow_run_list.workflow_runs);
+
+        if is_last_page {
+            break;
+        }
+    }
+
+    Some(workflow_runs)
+}
+
+// https://docs.github.com/en/rest/actions/workflow-jobs#list-jobs-for-a-workflow-run
+async fn list_workflow_run_jobs(
+    github_config: &GithubConfig,
+    run_id: u64,
+) -> Option<Vec<WorkflowJob>> {
+    let mut workflow_jobs = Vec::new();
+
+    for page in 1.. {
+        let workflow_job_list: WorkflowJobList = github_config
+            .get_repo_json(
+                format!(
+                    "actions/runs/{}/jobs?filter=latest&per_page={}&page={}",
+                    run_id, PAGE_SIZE, page
+                )
+                .as_str(),
+            )
+            .await?;
+        let is_last_page = workflow_job_list.jobs.len() < PAGE_SIZE;
+
+        workflow_jobs.extend(workflow_job_list.jobs);
+
+        if is_last_page {
+            break;
+        }
+    }
+
+    Some(workflow_jobs)
+}
+
+/// List every job of the active workflow runs of a repository, along with the user that triggered them.
+pub async fn list_active_jobs(github_config: &GithubConfig) -> Option<Vec<(User, WorkflowJob)>> {
+    let mut active_jobs = Vec::new();
+
+    // Queued jobs can also be part of a run that already started.
+    for status in &["queued", "in_progress"] {
+        for workflow_run in list_workflow_runs(github_config, status).await? {
+            for workflow_job in list_workflow_run_jobs(github_config, workflow_run.id).await? {
+                active_jobs.push((workflow_run.actor.clone(), workflow_job));
+            }
+        }
+    }
+
+    Some(active_jobs)
+}
+
+async fn poll_repository(github_config: &GithubConfig) -> Option<()> {
+    let repository: Repository = github_config.get_repo_json("").await?;
+    let pending_job_ids: HashSet<u64> = jobs::get_pending_jobs(
+        github_config.owner.as_str(),
+        github_config.repository.as_str(),
+    )
+    .iter()
+    .map(|job_info| job_info.job_id)
+    .collect();
+    let mut seen_job_ids = HashSet::new();
+
+    for (sender, workflow_job) in list_active_jobs(github_config).await? {
+        seen_job_ids.insert(workflow_job.id);
+
+        // Only completions of the jobs pending on this instance are relevant.
+        if workflow_job.status == "completed" && !pending_job_ids.contains(&workflow_job.id) {
+            continue;
+        }
+
+        dispatch_workflow_job_event(WorkflowJobEvent {
+            repository: repository.clone(),
+            sender,
+            workflow_job,
+        });
+    }
+
+    // Jobs of finished runs are not listed anymore, check them one by one.
+    for job_id in pending_job_ids.difference(&seen_job_ids) {
+        // https://docs.github.com/en/rest/actions/workflow-jobs#get-a-job-for-a-workflow-run
+        let workflow_job: WorkflowJob = github_config
+            .get_repo_json(format!("actions/jobs/{}", job_id).as_str())
+            .await?;
+
+        if workflow_job.status == "completed" {
+            dispatch_workflow_job_event(WorkflowJobEvent {
+                repository: repository.clone(),
+                sender: repository.owner.clone(),
+                workflow_job,
+            });
+        }
+    }
+
+    Some(())
+}
+
+pub async fn poll() {
+    for github_config in GLOBAL_GITHUB_CONFIG.iter() {
+        if !github_config.enabled {
+            continue;
+        }
+
+        if poll_repository(github_config).await.is_none() {
+            eprintln!(
+                "octoling: Cannot poll workflow jobs of {}/{}",
+                github_config.owner, github_config.repository
+            );
+        }
+    }
+}
+
+pub async fn poll_periodically() {
+    let poller_config = match &*GLOBAL_POLLER_CONFIG {
+        Some(poller_config) if poller_config.enabled => poller_config,
+        _ => return,
+    };
+    let interval = Duration::from_secs(poller_config.interval);
+
+    loop {
+        poll().await;
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -1,6 +1,6 @@
 use lxc_sys2::*;
 use os_pipe::{PipeReader, PipeWriter};
-use std::ffi::{CString, NulError};
+use std::ffi::{CStr, CString, NulError};
 use std::io::Read;
 use std::mem::ManuallyDrop;
 use std::os::raw::c_char;
@@ -60,6 +60,22 @@ extern "C" fn lxc_attach_run_command_wrapper(payload: *mut std::ffi::c_void) ->
     unsafe { lxc_attach_run_command(payload) }
 }
 
+/// Get an item of the LXC system configuration, like "lxc.lxcpath".
+pub fn get_global_config_item(key: &str) -> Option<String> {
+    let key_cstr = CString::new(key).ok()?;
+
+    unsafe {
+        // The value is owned by LXC.
+        let value = lxc_get_global_config_item(key_cstr.as_ptr());
+
+        if value.is_null() {
+            return None;
+        }
+
+        CStr::from_ptr(value).to_str().ok().map(String::from)
+    }
+}
+
 fn create_pipe() -> (PipeReader, PipeWriter) {
     os_pipe::pipe().unwrap()
 }
@@ -120,6 +136,39 @@ impl Container {
         }
     }
 
+    pub fn get_config_item(&self, key: &str) -> Result<String> {
+        let key_cstr = CString::new(key)?;
+
+        unsafe {
+            let value_size = ((*self.inner).get_config_item)(
+                self.inner,
+                key_cstr.as_ptr(),
+                std::ptr::null_mut(),
+                0,
+            );
+
+            if value_size < 0 {
+                return Err(ContainerError::Unknown);
+            }
+
+            let mut value = vec![0u8; value_size as usize + 1];
+            let value_size = ((*self.inner).get_config_item)(
+                self.inner,
+                key_cstr.as_ptr(),
+                value.as_mut_ptr() as *mut c_char,
+                value.len() as i32,
+            );
+
+            if value_size < 0 {
+                return Err(ContainerError::Unknown);
+            }
+
+            value.truncate(value_size as usize);
+
+            String::from_utf8(value).map_err(|_| ContainerError::Unknown)
+        }
+    }
+
     pub fn config_file_name(&self) -> Result<String> {
         unsafe {
             let config_file_name_raw = ((*self.inner).config_file_name)(self.inner);
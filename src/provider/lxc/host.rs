@@ -0,0 +1,81 @@
+use std::ffi::CString;
+use std::fs;
+use std::mem::MaybeUninit;
+
+use super::definition;
+
+// Default storage location of the containers.
+const DEFAULT_LXC_PATH: &str = "/var/lib/lxc";
+
+/// Value of a /proc/meminfo field in MiB.
+fn get_meminfo_value(field: &str) -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+
+    for line in meminfo.lines() {
+        if let Some(value) = line
+            .strip_prefix(field)
+            .and_then(|value| value.strip_prefix(':'))
+        {
+            let kilobytes: u64 = value.trim().trim_end_matches("kB").trim().parse().ok()?;
+
+            return Some(kilobytes / 1024);
+        }
+    }
+
+    None
+}
+
+/// Total memory of the host in MiB.
+pub fn get_total_memory() -> Option<u64> {
+    get_meminfo_value("MemTotal")
+}
+
+/// Memory available for new processes in MiB.
+pub fn get_available_memory() -> Option<u64> {
+    get_meminfo_value("MemAvailable")
+}
+
+/// Load average over the last minute.
+pub fn get_load_average() -> Option<f64> {
+    let loadavg = fs::read_to_string("/proc/loadavg").ok()?;
+
+    loadavg.split_whitespace().next()?.parse().ok()
+}
+
+pub fn get_cpu_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+}
+
+/// Storage location of the containers, as configured in the LXC system configuration.
+fn get_lxc_path() -> String {
+    definition::get_global_config_item("lxc.lxcpath")
+        .filter(|lxc_path| !lxc_path.is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_LXC_PATH))
+}
+
+/// Total and available space in MiB of the file system storing the containers.
+pub fn get_disk_space() -> Option<(u64, u64)> {
+    let path = CString::new(get_lxc_path()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+
+    let stat = unsafe { stat.assume_init() };
+
+    // The field types depend on the target.
+    #[allow(clippy::unnecessary_cast)]
+    let (blocks, available_blocks, block_size) = (
+        stat.f_blocks as u64,
+        stat.f_bavail as u64,
+        stat.f_frsize as u64,
+    );
+
+    Some((
+        blocks * block_size / (1024 * 1024),
+        available_blocks * block_size / (1024 * 1024),
+    ))
+}
@@ -1,13 +1,40 @@
 mod definition;
+mod host;
 
 use super::Provider;
 use super::ProviderError;
 use super::Result;
 use super::RunOptions;
 use super::Runner;
-use crate::config::ImageConfig;
+use crate::config::{ImageConfig, ResourcesConfig};
 
 use definition::*;
+use std::path::{Path, PathBuf};
+
+const ROOTFS_DIR_PREFIX: &str = "dir:";
+
+impl LxcRunner {
+    fn rootfs_path(&self) -> Result<PathBuf> {
+        let rootfs_path = self
+            .container
+            .get_config_item("lxc.rootfs.path")
+            .map_err(|_| ProviderError::Unknown(String::from("Cannot retrieve rootfs path!")))?;
+
+        // Only directory backed rootfs can be accessed from the host.
+        let rootfs_path = rootfs_path
+            .strip_prefix(ROOTFS_DIR_PREFIX)
+            .unwrap_or(rootfs_path.as_str());
+
+        if !rootfs_path.starts_with('/') {
+            return Err(ProviderError::Unknown(format!(
+                "Unsupported rootfs \"{}\"",
+                rootfs_path
+            )));
+        }
+
+        Ok(PathBuf::from(rootfs_path))
+    }
+}
 
 pub struct LxcRunner {
     container: Container,
@@ -51,6 +78,18 @@ impl Runner for LxcRunner {
 
         Err(ProviderError::RunnerRunFailed)
     }
+
+    fn push_file(&self, source: &Path, destination: &str) -> Result<()> {
+        let destination_path = self
+            .rootfs_path()?
+            .join(destination.trim_start_matches('/'));
+
+        if std::fs::copy(source, destination_path).is_err() {
+            return Err(ProviderError::RunnerPushFailed);
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -68,6 +107,49 @@ impl LxcProvider {
     }
 }
 
+/// Whether the host can fit a runner of the image on top of the resources `reserved` by the runners alive on it,
+/// among which the ones `provisioning` don't use their resources yet.
+pub fn has_room_for(
+    image_config: &ImageConfig,
+    reserved: &ResourcesConfig,
+    provisioning: &ResourcesConfig,
+) -> bool {
+    let resources = &image_config.resources;
+
+    // The declared footprints must fit in the host, and what is currently free must also fit the runners that
+    // don't use their resources yet, other processes or runners using more than declared can fill the host.
+    if resources.memory != 0 {
+        match (host::get_total_memory(), host::get_available_memory()) {
+            (Some(total_memory), Some(available_memory))
+                if reserved.memory + resources.memory <= total_memory
+                    && provisioning.memory + resources.memory <= available_memory => {}
+            _ => return false,
+        }
+    }
+
+    if resources.cpus != 0.0 {
+        let cpu_count = host::get_cpu_count() as f64;
+
+        match host::get_load_average() {
+            Some(load)
+                if reserved.cpus + resources.cpus <= cpu_count
+                    && load + provisioning.cpus + resources.cpus <= cpu_count => {}
+            _ => return false,
+        }
+    }
+
+    if resources.disk != 0 {
+        match host::get_disk_space() {
+            Some((total_disk, available_disk))
+                if reserved.disk + resources.disk <= total_disk
+                    && provisioning.disk + resources.disk <= available_disk => {}
+            _ => return false,
+        }
+    }
+
+    true
+}
+
 impl Provider for LxcProvider {
     fn destroy(&mut self, runner_id: &str) -> Result<()> {
         let runner = self.get_container(runner_id)?;
@@ -93,7 +175,7 @@ impl Provider for LxcProvider {
                         return Err(ProviderError::InvalidImage);
                     }
 
-                    let argv = vec!["--dist", args[0], "--release", args[1], "--arch", args[2]];
+                    let argv = ["--dist", args[0], "--release", args[1], "--arch", args[2]];
                     let result = container.create(template, &argv[..]);
 
                     if result.is_ok() {
@@ -1,7 +1,10 @@
 use once_cell::sync::Lazy;
 
-use crate::config::{ImageConfig, GLOBAL_PROVIDER_CONFIG};
+use crate::config::{
+    get_provider_config_by_id, ImageConfig, ResourcesConfig, GLOBAL_PROVIDER_CONFIG,
+};
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Mutex;
 
 pub type Result<T> = std::result::Result<T, ProviderError>;
@@ -15,6 +18,7 @@ pub enum ProviderError {
     RunnerStartFailed,
     RunnerStopFailed,
     RunnerRunFailed,
+    RunnerPushFailed,
     Unknown(String),
 }
 
@@ -54,6 +58,7 @@ pub trait Runner: Send {
     fn id(&self) -> Result<String>;
     fn start(&self) -> Result<()>;
     fn run(&self, args: &[&str], options: &RunOptions) -> Result<i32>;
+    fn push_file(&self, source: &Path, destination: &str) -> Result<()>;
     fn stop(&self) -> Result<()>;
 }
 
@@ -63,22 +68,53 @@ pub trait Provider: Send {
     fn destroy(&mut self, runner_id: &str) -> Result<()>;
 }
 
+fn create_provider(provider_type: &str) -> Option<Box<dyn Provider>> {
+    match provider_type {
+        #[cfg(target_os = "linux")]
+        "lxc" => Some(Box::new(lxc::LxcProvider)),
+        _ => None,
+    }
+}
+
+/// Whether the host of a provider can fit a runner of the image on top of the resources `reserved` by the
+/// runners alive on it, among which the ones `provisioning` don't use them yet, without waiting for the provider
+/// to be free.
+pub fn has_room_for(
+    provider_id: &str,
+    image_config: &ImageConfig,
+    reserved: &ResourcesConfig,
+    provisioning: &ResourcesConfig,
+) -> bool {
+    let provider_type = match get_provider_config_by_id(provider_id) {
+        Some(provider_config) => provider_config.provider_type,
+        None => return true,
+    };
+
+    match provider_type.as_str() {
+        #[cfg(target_os = "linux")]
+        "lxc" => lxc::has_room_for(image_config, reserved, provisioning),
+        _ => true,
+    }
+}
+
+/// Whether providers of the given type can be used on this platform.
+pub fn is_supported_type(provider_type: &str) -> bool {
+    create_provider(provider_type).is_some()
+}
+
 pub static GLOBAL_PROVIDER: Lazy<HashMap<String, Mutex<Box<dyn Provider>>>> = Lazy::new(|| {
     let mut providers = HashMap::new();
 
     for provider_config in &*GLOBAL_PROVIDER_CONFIG {
-        let provider: Box<dyn Provider> = match provider_config.provider_type.as_str() {
-            "lxc" => {
-                if cfg!(target_os = "linux") {
-                    Box::new(lxc::LxcProvider)
-                } else {
-                    unimplemented!("LXC provider is only availaible on Linux");
-                }
+        match create_provider(provider_config.provider_type.as_str()) {
+            Some(provider) => {
+                providers.insert(provider_config.id.clone(), Mutex::new(provider));
             }
-            _ => unimplemented!("{}", provider_config.provider_type),
-        };
-
-        providers.insert(provider_config.id.clone(), Mutex::new(provider));
+            None => eprintln!(
+                "octoling: Ignoring provider {} with unsupported type \"{}\"",
+                provider_config.id, provider_config.provider_type
+            ),
+        }
     }
 
     providers
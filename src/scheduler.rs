@@ -0,0 +1,447 @@
+use once_cell::sync::Lazy;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::{
+    self, GithubConfig, ImageConfig, ResourcesConfig, GLOBAL_MANAGER_CONFIG, GLOBAL_RETRY_CONFIG,
+};
+use crate::health;
+use crate::jobs::{self, JobStatus};
+use crate::manager::{self, ManagerError, RunnerInfo, RunnerState};
+use crate::provider::{self, ProviderError};
+use crate::shutdown;
+
+/// A runner to provision for a queued job once capacity allows it.
+#[derive(Clone, Debug)]
+pub struct RunnerRequest {
+    pub image_config: ImageConfig,
+    pub github_config: GithubConfig,
+    pub runner_id: String,
+    pub job_id: u64,
+    pub log_prefix: String,
+    /// Position in the queue among requests of the same priority.
+    sequence: u64,
+    /// Providers that already failed to create the runner.
+    failed_provider_ids: Vec<String>,
+    /// Number of times the runner was provisioned from scratch.
+    attempts: u32,
+}
+
+impl RunnerRequest {
+    pub fn new(
+        image_config: ImageConfig,
+        github_config: GithubConfig,
+        runner_id: String,
+        job_id: u64,
+        log_prefix: String,
+    ) -> Self {
+        RunnerRequest {
+            image_config,
+            github_config,
+            runner_id,
+            job_id,
+            log_prefix,
+            sequence: 0,
+            failed_provider_ids: Vec::new(),
+            attempts: 0,
+        }
+    }
+
+    /// Providers the runner can still be created with, in priority order.
+    fn get_candidate_provider_ids(&self) -> Vec<&str> {
+        self.image_config
+            .get_provider_ids()
+            .into_iter()
+            .filter(|provider_id| !self.failed_provider_ids.iter().any(|id| id == provider_id))
+            .filter(|provider_id| health::is_healthy(self.image_config.id.as_str(), provider_id))
+            .collect()
+    }
+}
+
+struct RunnerQueue {
+    requests: Vec<RunnerRequest>,
+    next_sequence: u64,
+}
+
+static GLOBAL_QUEUE: Lazy<Mutex<RunnerQueue>> = Lazy::new(|| {
+    Mutex::new(RunnerQueue {
+        requests: Vec::new(),
+        next_sequence: 0,
+    })
+});
+
+fn is_below(count: Option<&usize>, max_runners: Option<usize>) -> bool {
+    match max_runners {
+        Some(max_runners) => count.copied().unwrap_or(0) < max_runners,
+        None => true,
+    }
+}
+
+/// Number of runners per limited resource.
+#[derive(Default)]
+struct Usage {
+    total: usize,
+    providers: HashMap<String, usize>,
+    images: HashMap<String, usize>,
+    repositories: HashMap<(String, String), usize>,
+    /// Resources of the runners alive per provider, whether they use them yet or not.
+    reserved: HashMap<String, ResourcesConfig>,
+    /// Resources of the runners being provisioned per provider, the host doesn't account for them yet.
+    provisioning: HashMap<String, ResourcesConfig>,
+}
+
+impl Usage {
+    fn new(runner_infos: &[RunnerInfo]) -> Self {
+        let mut usage = Usage::default();
+
+        for runner_info in runner_infos {
+            usage.add(
+                runner_info.provider_id.as_str(),
+                runner_info.image_id.as_str(),
+                runner_info.owner.as_str(),
+                runner_info.repository.as_str(),
+            );
+
+            if let Some(image_config) = config::get_image_config_by_id(&runner_info.image_id) {
+                usage.reserve(
+                    runner_info.provider_id.as_str(),
+                    &image_config.resources,
+                    runner_info.state == RunnerState::Provisioning,
+                );
+            }
+        }
+
+        usage
+    }
+
+    fn add(&mut self, provider_id: &str, image_id: &str, owner: &str, repository: &str) {
+        self.total += 1;
+        *self.providers.entry(String::from(provider_id)).or_default() += 1;
+        *self.images.entry(String::from(image_id)).or_default() += 1;
+        *self
+            .repositories
+            .entry((String::from(owner), String::from(repository)))
+            .or_default() += 1;
+    }
+
+    fn reserve(&mut self, provider_id: &str, resources: &ResourcesConfig, is_provisioning: bool) {
+        self.reserved
+            .entry(String::from(provider_id))
+            .or_default()
+            .add(resources);
+
+        if is_provisioning {
+            self.provisioning
+                .entry(String::from(provider_id))
+                .or_default()
+                .add(resources);
+        }
+    }
+
+    fn has_room_for(&self, provider_id: &str, image_config: &ImageConfig) -> bool {
+        if image_config.resources.is_empty() {
+            return true;
+        }
+
+        let reserved = self.reserved.get(provider_id).cloned().unwrap_or_default();
+        let provisioning = self
+            .provisioning
+            .get(provider_id)
+            .cloned()
+            .unwrap_or_default();
+
+        provider::has_room_for(provider_id, image_config, &reserved, &provisioning)
+    }
+
+    fn has_provider_capacity_for(&self, provider_id: &str, image_config: &ImageConfig) -> bool {
+        let provider_max_runners = config::get_provider_config_by_id(provider_id)
+            .and_then(|provider_config| provider_config.max_runners);
+
+        is_below(self.providers.get(provider_id), provider_max_runners)
+            && self.has_room_for(provider_id, image_config)
+    }
+
+    fn has_capacity_for(&self, request: &RunnerRequest) -> bool {
+        let image_config = &request.image_config;
+        let github_config = &request.github_config;
+
+        is_below(Some(&self.total), GLOBAL_MANAGER_CONFIG.max_runners)
+            && is_below(self.images.get(&image_config.id), image_config.max_runners)
+            && is_below(
+                self.repositories.get(&(
+                    github_config.owner.clone(),
+                    github_config.repository.clone(),
+                )),
+                github_config.max_runners,
+            )
+    }
+}
+
+/// Whether a probe runner of the image fits in the limits of the provider, probes aren't tracked as runners.
+pub fn has_capacity_for_probe(image_config: &ImageConfig, provider_id: &str) -> bool {
+    let usage = Usage::new(&manager::get_runner_infos());
+
+    is_below(Some(&usage.total), GLOBAL_MANAGER_CONFIG.max_runners)
+        && is_below(usage.images.get(&image_config.id), image_config.max_runners)
+        && usage.has_provider_capacity_for(provider_id, image_config)
+}
+
+/// Whether a job still waits for a runner, a job cancelled meanwhile is completed.
+fn is_job_waiting(job_id: u64) -> bool {
+    jobs::get_job_info(job_id).is_some_and(|job_info| job_info.status == JobStatus::Queued)
+}
+
+async fn provision(mut request: RunnerRequest, provider_id: String) {
+    info!(
+        "{} Creating and starting runner {} with image {} on provider {}",
+        request.log_prefix, request.runner_id, request.image_config.id, provider_id
+    );
+
+    let result = manager::start_new_runner(
+        &request.image_config,
+        provider_id.as_str(),
+        request.github_config.clone(),
+        request.runner_id.as_str(),
+        request.job_id,
+    )
+    .await;
+
+    match &result {
+        Ok(_) => health::record_success(request.image_config.id.as_str(), provider_id.as_str()),
+        Err(error) if is_runner_failure(error) => {
+            health::record_failure(request.image_config.id.as_str(), provider_id.as_str())
+        }
+        Err(_) => {}
+    }
+
+    match result {
+        Ok(_) => info!(
+            "{} Started runner {}",
+            request.log_prefix, request.runner_id
+        ),
+        Err(ManagerError::Provider(
+            error @ (ProviderError::RunnerCreationFailed | ProviderError::RunnerStartFailed),
+        )) if has_other_candidate_provider(&request, provider_id.as_str())
+            && is_job_waiting(request.job_id) =>
+        {
+            eprintln!(
+                "{} Cannot start runner {} on provider {}: {:?}, falling back to the next provider",
+                request.log_prefix, request.runner_id, provider_id, error
+            );
+
+            request.failed_provider_ids.push(provider_id);
+            requeue(request);
+        }
+        Err(error) if is_retryable(&error) && is_job_waiting(request.job_id) => {
+            request.attempts += 1;
+
+            let retry_config = &*GLOBAL_RETRY_CONFIG;
+
+            if request.attempts >= retry_config.runner_attempts {
+                eprintln!(
+                    "{} Giving up on runner {} with image {} after {} attempts: {:?}",
+                    request.log_prefix,
+                    request.runner_id,
+                    request.image_config.id,
+                    request.attempts,
+                    error
+                );
+                return;
+            }
+
+            let backoff = retry_config.get_backoff(request.attempts);
+
+            eprintln!(
+                "{} Cannot start runner {} with image {} (attempt {}/{}): {:?}, retrying in {}s",
+                request.log_prefix,
+                request.runner_id,
+                request.image_config.id,
+                request.attempts,
+                retry_config.runner_attempts,
+                error,
+                backoff.as_secs()
+            );
+
+            tokio::time::sleep(backoff).await;
+
+            // The job might have been cancelled or picked up by another runner meanwhile.
+            if is_job_waiting(request.job_id) {
+                request.failed_provider_ids.clear();
+                requeue(request);
+            }
+        }
+        Err(error) => eprintln!(
+            "{} Cannot start runner {} with image {}: {:?}",
+            request.log_prefix, request.runner_id, request.image_config.id, error
+        ),
+    }
+}
+
+/// Whether the request can fall back to another provider than the one that just failed, which may still be a
+/// candidate if its health wasn't affected by the failure.
+fn has_other_candidate_provider(request: &RunnerRequest, provider_id: &str) -> bool {
+    request
+        .get_candidate_provider_ids()
+        .into_iter()
+        .any(|candidate_provider_id| candidate_provider_id != provider_id)
+}
+
+/// Whether an error tells that the image doesn't work on the provider.
+fn is_runner_failure(error: &ManagerError) -> bool {
+    matches!(
+        error,
+        ManagerError::Provider(_) | ManagerError::InstallationFailed
+    )
+}
+
+/// Whether provisioning the runner again could succeed.
+fn is_retryable(error: &ManagerError) -> bool {
+    !matches!(
+        error,
+        ManagerError::Cancelled
+            | ManagerError::ProviderNotFound
+            | ManagerError::Provider(ProviderError::InvalidImage)
+    )
+}
+
+/// Queue a runner request, it is provisioned right away if capacity allows it.
+pub fn submit(mut request: RunnerRequest) {
+    let runner_id = request.runner_id.clone();
+    let log_prefix = request.log_prefix.clone();
+
+    {
+        let mut queue = GLOBAL_QUEUE.lock().unwrap();
+
+        request.sequence = queue.next_sequence;
+        queue.next_sequence += 1;
+        queue.requests.push(request);
+    }
+
+    dispatch();
+
+    if !is_queued(runner_id.as_str()) {
+        return;
+    }
+
+    if shutdown::is_draining() {
+        info!(
+            "{} Waiting for the drain to be over to start runner {}",
+            log_prefix, runner_id
+        );
+    } else {
+        info!(
+            "{} Waiting for capacity to start runner {}",
+            log_prefix, runner_id
+        );
+    }
+}
+
+/// Put back a request at its original position in the queue.
+fn requeue(request: RunnerRequest) {
+    GLOBAL_QUEUE.lock().unwrap().requests.push(request);
+
+    dispatch();
+}
+
+/// Remove the request of a job from the queue, return false if it wasn't waiting.
+pub fn cancel(job_id: u64) -> bool {
+    let mut queue = GLOBAL_QUEUE.lock().unwrap();
+    let queue_length = queue.requests.len();
+
+    queue.requests.retain(|request| request.job_id != job_id);
+
+    queue.requests.len() != queue_length
+}
+
+/// Whether a runner request of a job waits in the queue.
+pub fn is_job_queued(job_id: u64) -> bool {
+    GLOBAL_QUEUE
+        .lock()
+        .unwrap()
+        .requests
+        .iter()
+        .any(|request| request.job_id == job_id)
+}
+
+fn is_queued(runner_id: &str) -> bool {
+    GLOBAL_QUEUE
+        .lock()
+        .unwrap()
+        .requests
+        .iter()
+        .any(|request| request.runner_id.as_str() == runner_id)
+}
+
+/// Provision every queued request that fits in the configured limits, by priority then in FIFO order.
+pub fn dispatch() {
+    if shutdown::is_draining() {
+        return;
+    }
+
+    let mut queue = GLOBAL_QUEUE.lock().unwrap();
+    let mut usage = Usage::new(&manager::get_runner_infos());
+
+    queue
+        .requests
+        .sort_by_key(|request| (Reverse(request.github_config.priority), request.sequence));
+
+    let mut index = 0;
+
+    while index < queue.requests.len() {
+        let request = &queue.requests[index];
+        let provider_id = if usage.has_capacity_for(request) {
+            request
+                .get_candidate_provider_ids()
+                .into_iter()
+                .find(|provider_id| {
+                    usage.has_provider_capacity_for(provider_id, &request.image_config)
+                })
+                .map(String::from)
+        } else {
+            None
+        };
+
+        // A request blocked by its own limits must not hold back the others.
+        let provider_id = match provider_id {
+            Some(provider_id) => provider_id,
+            None => {
+                index += 1;
+                continue;
+            }
+        };
+
+        let request = queue.requests.remove(index);
+
+        usage.add(
+            provider_id.as_str(),
+            request.image_config.id.as_str(),
+            request.github_config.owner.as_str(),
+            request.github_config.repository.as_str(),
+        );
+        usage.reserve(provider_id.as_str(), &request.image_config.resources, true);
+
+        // Count the runner right away, its provisioning task may not be running yet.
+        manager::reserve_runner(
+            &request.image_config,
+            provider_id.as_str(),
+            &request.github_config,
+            request.runner_id.as_str(),
+            request.job_id,
+        );
+
+        tokio::spawn(provision(request, provider_id));
+    }
+}
+
+/// Periodically retry the requests waiting for host resources, they aren't freed by runner destruction alone.
+pub async fn dispatch_periodically() {
+    let interval = Duration::from_secs(GLOBAL_MANAGER_CONFIG.dispatch_interval);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        dispatch();
+    }
+}
@@ -0,0 +1,239 @@
+use futures::channel::mpsc::{self, UnboundedReceiver};
+use futures::future::{self, BoxFuture, FutureExt, Shared};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::internal::pemfile;
+use tokio_rustls::rustls::{NoClientAuth, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use warp::Filter;
+
+use crate::api::{admin_socket_routes, api_routes, github_webhook_routes};
+use crate::config::GLOBAL_SERVER_CONFIG;
+use crate::shutdown;
+
+type ShutdownSignal = Shared<BoxFuture<'static, ()>>;
+
+#[cfg(unix)]
+fn bind_admin_socket(
+    path: &str,
+    signal: ShutdownSignal,
+) -> std::io::Result<BoxFuture<'static, ()>> {
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::net::UnixListener;
+
+    // Remove the socket left by a previous instance, but never another kind of file.
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)?;
+        }
+    }
+
+    let listener = UnixListener::bind(path)?;
+    let incoming = futures::stream::unfold(listener, |listener| async move {
+        let stream = listener.accept().await.map(|(stream, _)| stream);
+
+        Some((stream, listener))
+    });
+
+    Ok(warp::serve(admin_socket_routes())
+        .serve_incoming_with_graceful_shutdown(incoming, signal)
+        .boxed())
+}
+
+#[cfg(not(unix))]
+fn bind_admin_socket(
+    _path: &str,
+    _signal: ShutdownSignal,
+) -> std::io::Result<BoxFuture<'static, ()>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "unix sockets are not supported on this platform",
+    ))
+}
+
+/// Build the TLS configuration from PEM encoded certificate chain and private key.
+fn load_tls_config(cert: &[u8], key: &[u8]) -> Result<ServerConfig, String> {
+    let cert = pemfile::certs(&mut &*cert)
+        .ok()
+        .filter(|cert| !cert.is_empty())
+        .ok_or("no certificate found")?;
+    let key = match pemfile::pkcs8_private_keys(&mut &*key) {
+        Ok(keys) if !keys.is_empty() => keys,
+        _ => pemfile::rsa_private_keys(&mut &*key).unwrap_or_default(),
+    }
+    .into_iter()
+    .next()
+    .ok_or("no private key found")?;
+
+    let mut tls_config = ServerConfig::new(NoClientAuth::new());
+
+    tls_config
+        .set_single_cert(cert, key)
+        .map_err(|error| error.to_string())?;
+    tls_config.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+    Ok(tls_config)
+}
+
+/// Accept TLS connections until shutdown, handshakes are done aside so that a slow client doesn't hold back the
+/// others.
+fn accept_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    signal: ShutdownSignal,
+) -> UnboundedReceiver<io::Result<TlsStream<TcpStream>>> {
+    let (sender, receiver) = mpsc::unbounded();
+
+    tokio::spawn(async move {
+        loop {
+            let result = tokio::select! {
+                result = listener.accept() => result,
+                _ = signal.clone() => break,
+            };
+
+            let stream = match result {
+                Ok((stream, _)) => stream,
+                Err(error) => {
+                    eprintln!("octoling: Cannot accept connection: {}", error);
+
+                    // Errors like running out of file descriptors don't go away right away.
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            };
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                match acceptor.accept(stream).await {
+                    Ok(stream) => {
+                        let _ = sender.unbounded_send(Ok(stream));
+                    }
+                    Err(error) => debug!("octoling: TLS handshake failed: {}", error),
+                }
+            });
+        }
+    });
+
+    receiver
+}
+
+/// Serve the API and the webhooks until shutdown, `listen` overrides the configured addresses.
+///
+/// Return false if the server could not be started.
+pub async fn run(listen: Option<SocketAddr>) -> bool {
+    let server_config = &*GLOBAL_SERVER_CONFIG;
+    let addresses = match listen {
+        Some(listen) => vec![listen],
+        None => server_config.get_socket_addresses(),
+    };
+
+    let tls_acceptor = match &server_config.tls {
+        Some(tls_config) => {
+            let tls_server_config = match (
+                fs::read(&tls_config.cert_path),
+                fs::read(&tls_config.key_path),
+            ) {
+                (Ok(cert), Ok(key)) => load_tls_config(&cert, &key),
+                (Err(error), _) | (_, Err(error)) => Err(error.to_string()),
+            };
+
+            match tls_server_config {
+                Ok(tls_server_config) => Some(TlsAcceptor::from(Arc::new(tls_server_config))),
+                Err(error) => {
+                    eprintln!(
+                        "octoling: Cannot load TLS certificate or key ({}, {}): {}",
+                        tls_config.cert_path, tls_config.key_path, error
+                    );
+
+                    return false;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let routes = api_routes().or(github_webhook_routes());
+    let signal = shutdown::wait_for_shutdown().boxed().shared();
+    let mut servers = Vec::new();
+
+    for address in addresses {
+        match &tls_acceptor {
+            Some(tls_acceptor) => {
+                let listener = match TcpListener::bind(address).await {
+                    Ok(listener) => listener,
+                    Err(error) => {
+                        eprintln!("octoling: Cannot listen on {}: {}", address, error);
+
+                        return false;
+                    }
+                };
+
+                info!(
+                    "octoling: Listening on https://{}",
+                    listener.local_addr().unwrap_or(address)
+                );
+
+                let incoming = accept_tls(listener, tls_acceptor.clone(), signal.clone());
+
+                servers.push(
+                    warp::serve(routes.clone())
+                        .serve_incoming_with_graceful_shutdown(incoming, signal.clone())
+                        .boxed(),
+                );
+            }
+            None => {
+                match warp::serve(routes.clone())
+                    .try_bind_with_graceful_shutdown(address, signal.clone())
+                {
+                    Ok((address, server)) => {
+                        info!("octoling: Listening on http://{}", address);
+
+                        servers.push(server.boxed());
+                    }
+                    Err(error) => {
+                        eprintln!("octoling: Cannot listen on {}: {}", address, error);
+
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(admin_socket) = &server_config.admin_socket {
+        match bind_admin_socket(admin_socket.as_str(), signal.clone()) {
+            Ok(server) => {
+                info!("octoling: Admin API listening on {}", admin_socket);
+
+                servers.push(server);
+            }
+            Err(error) => {
+                eprintln!(
+                    "octoling: Cannot listen on admin socket {}: {}",
+                    admin_socket, error
+                );
+
+                return false;
+            }
+        }
+    }
+
+    future::join_all(servers).await;
+
+    if let Some(admin_socket) = &server_config.admin_socket {
+        if let Err(error) = fs::remove_file(admin_socket) {
+            eprintln!(
+                "octoling: Cannot remove admin socket {}: {}",
+                admin_socket, error
+            );
+        }
+    }
+
+    true
+}
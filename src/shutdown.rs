@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::{ShutdownRunnerPolicy, GLOBAL_SHUTDOWN_CONFIG};
+use crate::manager::{self, RunnerState};
+use crate::scheduler;
+
+const WAIT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// No new runner is provisioned while draining, for host maintenance or before shutting down.
+static GLOBAL_DRAINING: AtomicBool = AtomicBool::new(false);
+
+pub fn is_draining() -> bool {
+    GLOBAL_DRAINING.load(Ordering::SeqCst)
+}
+
+pub fn set_draining(draining: bool) {
+    if GLOBAL_DRAINING.swap(draining, Ordering::SeqCst) == draining {
+        return;
+    }
+
+    if draining {
+        info!("octoling: Draining, queued jobs wait until the drain is over");
+    } else {
+        info!("octoling: Drain mode disabled, starting runners again");
+
+        // Start the runners that were waiting in the queue.
+        scheduler::dispatch();
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_terminate() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(_) => std::future::pending().await,
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_terminate() {
+    std::future::pending().await
+}
+
+/// Wait until a runner state matches or the deadline is reached, return false on timeout.
+async fn wait_for_runners<F>(deadline: Instant, predicate: F) -> bool
+where
+    F: Fn(RunnerState) -> bool,
+{
+    loop {
+        let runner_count = manager::get_runner_infos()
+            .iter()
+            .filter(|runner_info| predicate(runner_info.state))
+            .count();
+
+        if runner_count == 0 {
+            return true;
+        }
+
+        if Instant::now() >= deadline {
+            return false;
+        }
+
+        tokio::time::sleep(WAIT_CHECK_INTERVAL).await;
+    }
+}
+
+/// Wait for Ctrl-C or SIGTERM, then drain the runners before letting the server stop.
+///
+/// The server keeps running meanwhile so that completed jobs are still handled.
+pub async fn wait_for_shutdown() {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = wait_for_terminate() => {}
+    }
+
+    info!("octoling: Shutting down");
+
+    set_draining(true);
+
+    let shutdown_config = &*GLOBAL_SHUTDOWN_CONFIG;
+    let deadline = Instant::now() + Duration::from_secs(shutdown_config.timeout);
+
+    // Runners being provisioned are rolled back by their provisioning task.
+    let cancelled_count = manager::cancel_provisioning_runners();
+
+    if cancelled_count != 0 {
+        info!(
+            "octoling: Rolling back {} runners being provisioned",
+            cancelled_count
+        );
+    }
+
+    wait_for_runners(deadline, |state| state == RunnerState::Provisioning).await;
+
+    match shutdown_config.runners {
+        ShutdownRunnerPolicy::Keep => return,
+        ShutdownRunnerPolicy::Wait => {
+            info!(
+                "octoling: Waiting up to {}s for active runners to finish",
+                shutdown_config.timeout
+            );
+
+            if wait_for_runners(deadline, |_| true).await {
+                return;
+            }
+
+            eprintln!("octoling: Shutdown timeout reached, destroying the remaining runners");
+        }
+        ShutdownRunnerPolicy::Destroy => {}
+    }
+
+    manager::destroy_all_runners().await;
+}
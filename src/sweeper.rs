@@ -0,0 +1,92 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::api::{dispatch_workflow_job_event, Repository, WorkflowJobEvent};
+use crate::config::{self, GithubConfig, GLOBAL_GITHUB_CONFIG, GLOBAL_SWEEP_CONFIG};
+use crate::jobs;
+use crate::poller;
+
+/// Provision runners for missed queued jobs, newly seen ones are recorded to be rechecked on the next sweep.
+async fn sweep_repository(
+    github_config: &GithubConfig,
+    previous_candidate_job_ids: &HashSet<u64>,
+    candidate_job_ids: &mut HashSet<u64>,
+) -> Option<()> {
+    let mut repository: Option<Repository> = None;
+
+    for (sender, workflow_job) in poller::list_active_jobs(github_config).await? {
+        let is_handled_by_instance =
+            !config::get_image_configs_by_labels(&workflow_job.labels).is_empty();
+
+        if workflow_job.status != "queued"
+            || !is_handled_by_instance
+            || jobs::is_job_known(workflow_job.id)
+        {
+            continue;
+        }
+
+        // Give webhook deliveries one full sweep interval to arrive before acting.
+        if !previous_candidate_job_ids.contains(&workflow_job.id) {
+            candidate_job_ids.insert(workflow_job.id);
+            continue;
+        }
+
+        if repository.is_none() {
+            repository = Some(github_config.get_repo_json("").await?);
+        }
+
+        info!(
+            "octoling: Job #{} ({}/{}): missed queued event, recovering",
+            workflow_job.id, github_config.owner, github_config.repository
+        );
+
+        dispatch_workflow_job_event(WorkflowJobEvent {
+            repository: repository.clone()?,
+            sender,
+            workflow_job,
+        });
+    }
+
+    Some(())
+}
+
+pub async fn sweep(previous_candidate_job_ids: &HashSet<u64>) -> HashSet<u64> {
+    let mut candidate_job_ids = HashSet::new();
+
+    for github_config in GLOBAL_GITHUB_CONFIG.iter() {
+        if !github_config.enabled {
+            continue;
+        }
+
+        if sweep_repository(
+            github_config,
+            previous_candidate_job_ids,
+            &mut candidate_job_ids,
+        )
+        .await
+        .is_none()
+        {
+            eprintln!(
+                "octoling: Cannot sweep queued jobs of {}/{}",
+                github_config.owner, github_config.repository
+            );
+        }
+    }
+
+    candidate_job_ids
+}
+
+pub async fn sweep_periodically() {
+    let sweep_config = match &*GLOBAL_SWEEP_CONFIG {
+        Some(sweep_config) if sweep_config.enabled => sweep_config,
+        _ => return,
+    };
+    let interval = Duration::from_secs(sweep_config.interval);
+    let mut candidate_job_ids = HashSet::new();
+
+    loop {
+        candidate_job_ids = sweep(&candidate_job_ids).await;
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -1 +1,104 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
 
+/// A set forgetting its values after a retention period, or once it grows over its capacity.
+pub struct ExpiringSet<T> {
+    values: HashMap<T, Instant>,
+    insertion_order: VecDeque<(T, Instant)>,
+    retention: Duration,
+    capacity: usize,
+}
+
+impl<T: Clone + Eq + Hash> ExpiringSet<T> {
+    pub fn new(retention: Duration, capacity: usize) -> Self {
+        ExpiringSet {
+            values: HashMap::new(),
+            insertion_order: VecDeque::new(),
+            retention,
+            capacity,
+        }
+    }
+
+    fn prune(&mut self) {
+        let now = Instant::now();
+
+        while let Some((value, inserted_at)) = self.insertion_order.front() {
+            let is_expired = now.duration_since(*inserted_at) > self.retention;
+
+            if !is_expired && self.insertion_order.len() <= self.capacity {
+                break;
+            }
+
+            self.values.remove(value);
+            self.insertion_order.pop_front();
+        }
+    }
+
+    pub fn contains(&mut self, value: &T) -> bool {
+        self.prune();
+
+        self.values.contains_key(value)
+    }
+
+    /// Insert a value, return false if it was already present.
+    pub fn insert(&mut self, value: T) -> bool {
+        if self.contains(&value) {
+            return false;
+        }
+
+        let now = Instant::now();
+
+        self.values.insert(value.clone(), now);
+        self.insertion_order.push_back((value, now));
+        self.prune();
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_rejects_duplicates() {
+        let mut set = ExpiringSet::new(Duration::from_secs(60), 10);
+
+        assert!(set.insert("a"));
+        assert!(set.insert("b"));
+        assert!(!set.insert("a"));
+        assert!(set.contains(&"a"));
+        assert!(set.contains(&"b"));
+        assert!(!set.contains(&"c"));
+    }
+
+    #[test]
+    fn oldest_values_are_evicted_over_capacity() {
+        let mut set = ExpiringSet::new(Duration::from_secs(60), 2);
+
+        assert!(set.insert(1));
+        assert!(set.insert(2));
+        assert!(set.insert(3));
+
+        assert!(!set.contains(&1));
+        assert!(set.contains(&2));
+        assert!(set.contains(&3));
+
+        // An evicted value can be inserted again.
+        assert!(set.insert(1));
+        assert!(!set.contains(&2));
+    }
+
+    #[test]
+    fn values_expire_after_retention() {
+        let mut set = ExpiringSet::new(Duration::from_millis(10), 10);
+
+        assert!(set.insert("a"));
+
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(!set.contains(&"a"));
+        assert!(set.insert("a"));
+    }
+}